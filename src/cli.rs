@@ -1,28 +1,49 @@
 use std::{env, error::Error, path::PathBuf, process::exit, sync::Arc, thread};
 
-use axum::Router;
+use axum::{
+    extract::{ConnectInfo, Request},
+    middleware, Router,
+};
 use std::io::Write;
-use tokio::runtime::Handle;
 
-use crate::service::{config::Config, ImHumane};
+use crate::http::cors::CorsConfig;
+use crate::service::{config::Config, ImHumane, Labels};
 
 use clap::{crate_authors, crate_description, crate_version, Arg, ArgAction, Command};
+use tokio_listener::SomeSocketAddrClonable;
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct AppConfig {
     listener_address: tokio_listener::ListenerAddress,
     images_directory: PathBuf,
+    /// Directory of words read out by audio challenges, with a directory of WAV clips per word.
+    #[serde(default)]
+    audio_directory: Option<PathBuf>,
     threads: usize,
+    /// JSON file with a list of site definitions.
+    #[serde(default)]
+    sites_file: Option<PathBuf>,
+    /// JSON file the selections of unlabelled images are kept in across restarts.
+    #[serde(default)]
+    labels_file: Option<PathBuf>,
 }
 
-fn parse_config<'a, T: serde::Deserialize<'a>>(prefix: &str) -> Result<T, Box<dyn Error>> {
-    let cfg_source = config::Config::builder()
-        .add_source(
-            config::Environment::with_prefix(prefix)
-                .convert_case(config::Case::ScreamingSnake)
-                .try_parsing(true),
-        )
-        .build()?;
+fn parse_config<'a, T: serde::Deserialize<'a>>(
+    prefix: &str,
+    list_keys: &[&str],
+) -> Result<T, Box<dyn Error>> {
+    let mut environment = config::Environment::with_prefix(prefix)
+        .convert_case(config::Case::ScreamingSnake)
+        .try_parsing(true);
+
+    if !list_keys.is_empty() {
+        environment = environment.list_separator(",");
+        for key in list_keys {
+            environment = environment.with_list_parse_key(key);
+        }
+    }
+
+    let cfg_source = config::Config::builder().add_source(environment).build()?;
 
     cfg_source.try_deserialize().map_err(|err| {
         tracing::error!("Error in the provided configuration: {}", err);
@@ -30,8 +51,24 @@ fn parse_config<'a, T: serde::Deserialize<'a>>(prefix: &str) -> Result<T, Box<dy
     })
 }
 
-fn app(service: Arc<ImHumane>) -> Router {
-    crate::http::get_router(service)
+fn app(service: Arc<ImHumane>, cors_config: &CorsConfig) -> Router {
+    let cors = cors_config.layer(service.clone()).unwrap_or_else(|err| {
+        tracing::error!("Error in the CORS configuration: {}", err);
+        exit(2);
+    });
+    crate::http::get_router(service, cors).layer(middleware::map_request(tcp_connect_info))
+}
+
+/// Exposes the peer address of TCP connections as a plain `SocketAddr` for the HTTP handlers.
+async fn tcp_connect_info(mut request: Request) -> Request {
+    if let Some(ConnectInfo(SomeSocketAddrClonable::Tcp(addr))) = request
+        .extensions()
+        .get::<ConnectInfo<SomeSocketAddrClonable>>()
+        .cloned()
+    {
+        request.extensions_mut().insert(ConnectInfo(addr));
+    }
+    request
 }
 
 fn setup_logger() {
@@ -78,6 +115,12 @@ pub(crate) async fn main() {
                 .long("check")
                 .help("Check the configuration"),
         )
+        .arg(
+            Arg::new("export-labels")
+                .action(ArgAction::SetTrue)
+                .long("export-labels")
+                .help("Print the unlabelled images proposed for collections as JSON"),
+        )
         .version(crate_version!())
         .author(crate_authors!("\n"));
 
@@ -85,15 +128,28 @@ pub(crate) async fn main() {
 
     setup_logger();
 
-    let app_config: AppConfig = parse_config("IMHUMANE").unwrap();
-    let config: Config = parse_config("IMHUMANE").unwrap();
-    let user_opts: tokio_listener::UserOptions = parse_config("IMHUMANE_LISTENER").unwrap();
+    let app_config: AppConfig = parse_config("IMHUMANE", &[]).unwrap();
+    let config: Config = parse_config("IMHUMANE", &Config::LIST_KEYS).unwrap();
+    let user_opts: tokio_listener::UserOptions = parse_config("IMHUMANE_LISTENER", &[]).unwrap();
+    let cors_config: CorsConfig = parse_config("IMHUMANE_CORS", &CorsConfig::LIST_KEYS).unwrap();
 
     if config.buffer_size < 1 {
         tracing::error!("Buffer size must be >= 1");
         exit(2);
     }
 
+    // Odd one out challenges need room for a majority and an intruder
+    if config.grid_length < 2 {
+        tracing::error!("Grid length must be >= 2");
+        exit(2);
+    }
+
+    // Rate limits count the requests per minute the risk scorer remembers
+    if config.risk_window < 60 {
+        tracing::error!("Risk window must be >= 60");
+        exit(2);
+    }
+
     if app_config.threads < 1 {
         tracing::error!("Threads must be >= 1");
         exit(2);
@@ -104,21 +160,54 @@ pub(crate) async fn main() {
         exit(0);
     }
 
+    if args.get_flag("export-labels") {
+        let Some(labels_file) = &app_config.labels_file else {
+            tracing::error!("Exporting labels needs a labels file");
+            exit(2);
+        };
+        let labels = Labels::new(config.label_policy());
+        labels.load(labels_file).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            exit(2);
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&labels.proposals()).unwrap()
+        );
+        exit(0);
+    }
+
     let service = Arc::new(ImHumane::from(&config));
     service
         .scan_for_collections(&app_config.images_directory)
         .unwrap();
 
+    if let Some(audio_directory) = &app_config.audio_directory {
+        service.scan_for_audio(audio_directory).unwrap();
+    }
+
+    if let Some(labels_file) = &app_config.labels_file {
+        service.load_labels(labels_file).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            exit(2);
+        });
+    }
+
+    if let Some(sites_file) = &app_config.sites_file {
+        service.load_sites(sites_file).unwrap_or_else(|err| {
+            tracing::error!("{}", err);
+            exit(2);
+        });
+    }
+
     // Start threads for the challenge generators
-    let handle = Handle::try_current().expect("Failed to get handle for current tokio runtime");
     let mut threads = Vec::new();
     for _ in 1..app_config.threads {
         let svc = service.clone();
-        let handle = handle.clone();
-        threads.push(thread::spawn(move || svc.run_generator(handle)))
+        threads.push(thread::spawn(move || svc.run_generator()))
     }
 
-    let app = app(service);
+    let app = app(service, &cors_config);
 
     // Start the web server
     let listener = tokio_listener::Listener::bind(
@@ -134,9 +223,12 @@ pub(crate) async fn main() {
     .unwrap();
 
     tracing::info!("Listening on {}", app_config.listener_address);
-    tokio_listener::axum07::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    tokio_listener::axum07::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SomeSocketAddrClonable>(),
+    )
+    .await
+    .unwrap();
 
     threads.into_iter().for_each(|t| t.join().unwrap());
 }
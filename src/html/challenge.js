@@ -21,10 +21,36 @@ function asleep(delay) {
     return new Promise((resolve) => setTimeout(resolve, delay));
 }
 
-async function fetchChallenge() {
-    const response = await fetch(IMHUMANE_API_ROUTE, {
+function leadingZeroBits(bytes) {
+    let bits = 0;
+    for (const byte of bytes) {
+        if (byte === 0) {
+            bits += 8;
+            continue;
+        }
+        return bits + Math.clz32(byte) - 24;
+    }
+    return bits;
+}
+
+async function fetchChallenge(dataset, session, kind) {
+    const url = new URL(IMHUMANE_API_ROUTE, document.baseURI);
+    for (const key of ["sitekey", "action", "cdata"]) {
+        if (dataset[key]) url.searchParams.set(key, dataset[key]);
+    }
+    if (session) url.searchParams.set("session", session);
+    if (dataset.tiles === "true") url.searchParams.set("tiles", "true");
+    if (kind) url.searchParams.set("kind", kind);
+    const response = await fetch(url, {
         method: "GET",
     });
+    if (response.headers.get("X-Imhumane-Kind") === "text") {
+        return new Challenge(response.headers, null, await response.json());
+    }
+    if (response.headers.get("X-Imhumane-Kind") === "proof_of_work"
+        || response.headers.has("X-Imhumane-Tile-Urls")) {
+        return new Challenge(response.headers, null);
+    }
     const image = await blobToBase64(await response.blob());
     return new Challenge(response.headers, image);
 }
@@ -32,19 +58,57 @@ async function fetchChallenge() {
 class Challenge {
     constructor(
         headers,
-        base64Image
+        base64Image,
+        descriptions
     ) {
         this.challengeId = headers.get("X-Imhumane-Id");
+        this.kind = headers.get("X-Imhumane-Kind") || "grid";
+        this.nonce = headers.get("X-Imhumane-Nonce");
+        this.powBits = +headers.get("X-Imhumane-Pow-Bits");
         this.topic = headers.get("X-Imhumane-Topic");
-        this.gapSize = +headers.get("X-Imhumane-Gap-Size");
         this.imageSize = +headers.get("X-Imhumane-Image-Size");
-        this.gridLength = +headers.get("X-Imhumane-Grid-Length");
-        this.imageUrl = `url("${base64Image}")`;
+        this.intruders = +headers.get("X-Imhumane-Intruders");
+        this.words = +headers.get("X-Imhumane-Words");
+        const [collageWidth, collageHeight] = (headers.get("X-Imhumane-Collage-Size") || "0x0").split("x");
+        this.collageWidth = +collageWidth;
+        this.collageHeight = +collageHeight;
+        this.tiles = (headers.get("X-Imhumane-Tiles") || "").split(";").filter(tile => tile)
+            .map(tile => {
+                const [x, y, width, height] = tile.split(",").map(Number);
+                return { x, y, width, height };
+            });
+        const exampleUrl = headers.get("X-Imhumane-Example-Url");
+        this.exampleUrl = exampleUrl && new URL(`${IMHUMANE_API_URL}${exampleUrl}`, document.baseURI).toString();
+        // Tiles sent separately, in the same order
+        this.tileUrls = (headers.get("X-Imhumane-Tile-Urls") || "").split(";").filter(url => url)
+            .map(url => new URL(`${IMHUMANE_API_URL}${url}`, document.baseURI).toString());
+        this.imageUrl = base64Image ? `url("${base64Image}")` : "none";
+        // Audio challenges send a recording in place of the image
+        this.audioUrl = this.kind === "audio" ? base64Image : null;
+        // Text challenges send descriptions in place of the image
+        this.descriptions = descriptions || [];
+    }
+
+    /**
+     * Find an answer to a proof-of-work challenge
+     * @returns {Promise<String>}
+     */
+    async solveProofOfWork() {
+        const encoder = new TextEncoder();
+        for (let counter = 0; ; counter++) {
+            const answer = counter.toString(16);
+            const hash = await crypto.subtle.digest("SHA-256", encoder.encode(this.nonce + answer));
+            if (leadingZeroBits(new Uint8Array(hash)) >= this.powBits) {
+                return answer;
+            }
+        }
     }
 
     /**
      * Validate the users's answer
-     * @param {String} answer
+     * @param {String|Object} answer text, or `{ x, y }` for click challenges
+     * @returns {Promise<Object|null>} `{ done: true }` once a token is issued,
+     *     the next round's progress if more rounds are needed, or null if wrong
      */
     async validate(answer) {
         const body = JSON.stringify({ answer, challenge_id: this.challengeId });
@@ -56,9 +120,12 @@ class Challenge {
             }
         });
         if (response.status == 204) {
-            return true;
+            return { done: true };
+        }
+        if (response.status == 202) {
+            return { done: false, ...(await response.json()) };
         }
-        return false;
+        return null;
     }
 }
 
@@ -73,20 +140,52 @@ class ChallengeGrid {
         this.challenge = challenge;
 
         const cssClass = container.cssClass;
-        const { gapSize, imageSize, gridLength } = challenge;
+        const { collageWidth, collageHeight, tiles } = challenge;
 
         // Elements
         this.title = newElement("p", "imhumane-title");
-        this.title.innerHTML = `Select all images containing <br /><b>${challenge.topic}</b>`;
+        if (challenge.kind === "odd_one_out") {
+            this.title.innerHTML = challenge.intruders > 1
+                ? `Select the <b>${challenge.intruders}</b> images which don't belong`
+                : "Select the image which doesn't belong";
+        } else if (challenge.exampleUrl) {
+            // Shown instead of the topic, so it reads the same in any language
+            this.title.innerHTML = "Select all images like<br />";
+            const example = newElement("img", "imhumane-example");
+            example.alt = "";
+            example.src = challenge.exampleUrl;
+            this.title.appendChild(example);
+        } else {
+            this.title.innerHTML = `Select all images containing <br /><b>${challenge.topic}</b>`;
+        }
+
+        // Place elements over each tile, relative to the collage so they scale with it
+        const placeOnTile = (elem, tile) => {
+            elem.style.left = `${(tile.x / collageWidth) * 100}%`;
+            elem.style.top = `${(tile.y / collageHeight) * 100}%`;
+            elem.style.width = `${(tile.width / collageWidth) * 100}%`;
+            elem.style.height = `${(tile.height / collageHeight) * 100}%`;
+        };
 
-        this.checkboxElements = [];
-        for (let i = 1; i <= gridLength ** 2; i++) {
+        this.checkboxElements = tiles.map(tile => {
             const elem = newElement("input", "imhumane-checkbox");
             elem.type = "checkbox";
-            this.checkboxElements.push(elem);
-        }
+            placeOnTile(elem, tile);
+            return elem;
+        });
+
+        // Tiles sent separately load one by one instead of as one collage
+        this.tileElements = challenge.tileUrls.map((url, i) => {
+            const elem = newElement("img", "imhumane-tile");
+            elem.loading = "lazy";
+            elem.alt = "";
+            elem.src = url;
+            placeOnTile(elem, tiles[i]);
+            return elem;
+        });
 
         this.grid = newElement("div", "imhumane-grid");
+        this.tileElements.forEach(elem => this.grid.appendChild(elem));
         this.checkboxElements.forEach(elem => this.grid.appendChild(elem));
 
         this.actions = newElement("span", "imhumane-actions");
@@ -101,43 +200,405 @@ class ChallengeGrid {
         });
 
         // Styling
-        const containerLength = gapSize + ((gapSize + imageSize) * gridLength);
-        const gapPercentage = (((gapSize * (gridLength + 1)) / containerLength) / 4) * 100;
+        this.cssStyle = `
+            .${cssClass}:not(.imhumane-responsive) .imhumane-body > * {
+                width: ${collageWidth}px;
+            }
+
+            .${cssClass} .imhumane-grid {
+                position: relative;
+                background-size: contain;
+                background-image: ${challenge.imageUrl};
+                aspect-ratio: ${collageWidth} / ${collageHeight};
+                box-sizing: border-box;
+            }
+
+            .${cssClass} .imhumane-grid .imhumane-checkbox, .${cssClass} .imhumane-grid .imhumane-tile {
+                position: absolute;
+            }
+
+            .${cssClass} .imhumane-example {
+                display: block;
+                margin: 0.5em auto;
+                max-width: 50%;
+            }
+        `;
+    }
+
+    render() {
+        const root = this.container.body;
+        root.appendChild(this.title);
+        root.appendChild(this.grid);
+        root.appendChild(this.actions);
+    }
+
+    hide() {
+        this.container.body.innerHTML = "";
+    }
+
+    readAnswer() {
+        return this.checkboxElements.reduce((prev, elem) =>
+            prev + (elem.checked && "1" || "0")
+            , "");
+    }
+
+    reset() {
+        this.checkboxElements.forEach(elem => { elem.checked = false; });
+        this.button.disabled = false;
+    }
+
+    waitForAnswer() {
+        return new Promise((resolve) => {
+            this.button.addEventListener("click", () => {
+                resolve(this.readAnswer());
+            });
+        });
+    }
+}
+
+class ChallengeRotate {
+    /**
+     *
+     * @param {ChallengeContainer} container
+     * @param {Challenge} challenge
+     */
+    constructor(container, challenge) {
+        this.container = container;
+        this.challenge = challenge;
+
+        const cssClass = container.cssClass;
+        const { imageSize } = challenge;
+
+        // Elements
+        this.title = newElement("p", "imhumane-title");
+        this.title.innerHTML = "Rotate the image until it is <br /><b>upright</b>";
+
+        this.image = newElement("div", "imhumane-rotate-image");
+
+        this.slider = newElement("input", "imhumane-rotate-slider");
+        this.slider.type = "range";
+        this.slider.min = 0;
+        this.slider.max = 359;
+        this.slider.value = 0;
+        this.slider.addEventListener("input", () => {
+            this.image.style.transform = `rotate(${this.slider.value}deg)`;
+        });
+
+        this.actions = newElement("span", "imhumane-actions");
+        this.button = newElement("button", "imhumane-action-submit");
+        this.button.innerText = "Validate";
+        this.button.type = "button";
+        this.actions.appendChild(this.button);
+
+        // Disable button on click
+        this.button.addEventListener("click", () => {
+            this.button.disabled = true;
+        });
 
+        // Styling
         this.cssStyle = `
-            .${this.cssClass}:not(.imhumane-responsive) div {
-                width: ${this.containerLength}px;
+            .${cssClass}:not(.imhumane-responsive) .imhumane-body > * {
+                width: ${imageSize}px;
+            }
+
+            .${cssClass} .imhumane-rotate-image {
+                background-size: contain;
+                background-image: ${challenge.imageUrl};
+                aspect-ratio: 1;
+                box-sizing: border-box;
             }
 
-            .${cssClass} .imhumane-body > * {
-                gap: ${gapPercentage}%;
-                padding: ${gapPercentage}%;
+            .${cssClass} .imhumane-rotate-slider {
+                display: block;
+                box-sizing: border-box;
+                margin: 0.5em 0;
             }
+        `;
+    }
+
+    render() {
+        const root = this.container.body;
+        root.appendChild(this.title);
+        root.appendChild(this.image);
+        root.appendChild(this.slider);
+        root.appendChild(this.actions);
+    }
+
+    hide() {
+        this.container.body.innerHTML = "";
+    }
+
+    readAnswer() {
+        // Clockwise degrees, which is the way CSS rotates
+        return this.slider.value;
+    }
+
+    reset() {
+        this.slider.value = 0;
+        this.image.style.transform = "";
+        this.button.disabled = false;
+    }
+
+    waitForAnswer() {
+        return new Promise((resolve) => {
+            this.button.addEventListener("click", () => {
+                resolve(this.readAnswer());
+            });
+        });
+    }
+}
+
+class ChallengeClick {
+    /**
+     *
+     * @param {ChallengeContainer} container
+     * @param {Challenge} challenge
+     */
+    constructor(container, challenge) {
+        this.container = container;
+        this.challenge = challenge;
+
+        const cssClass = container.cssClass;
+        const { imageSize } = challenge;
+
+        // Elements
+        this.title = newElement("p", "imhumane-title");
+        this.title.innerHTML = `Click the <br /><b>${challenge.topic}</b>`;
+
+        this.image = newElement("div", "imhumane-click-image");
+        this.marker = newElement("span", "imhumane-click-marker");
+        this.marker.hidden = true;
+        this.image.appendChild(this.marker);
+        this.point = null;
+        this.image.addEventListener("click", (event) => {
+            // Scale to image pixels, as the image may be shown at another size
+            const bounds = this.image.getBoundingClientRect();
+            this.point = {
+                x: Math.round((event.clientX - bounds.left) * imageSize / bounds.width),
+                y: Math.round((event.clientY - bounds.top) * imageSize / bounds.height),
+            };
+            this.marker.style.left = `${(this.point.x / imageSize) * 100}%`;
+            this.marker.style.top = `${(this.point.y / imageSize) * 100}%`;
+            this.marker.hidden = false;
+            this.button.disabled = false;
+        });
+
+        this.actions = newElement("span", "imhumane-actions");
+        this.button = newElement("button", "imhumane-action-submit");
+        this.button.innerText = "Validate";
+        this.button.type = "button";
+        this.button.disabled = true;
+        this.actions.appendChild(this.button);
+
+        // Disable button on click
+        this.button.addEventListener("click", () => {
+            this.button.disabled = true;
+        });
 
+        // Styling
+        this.cssStyle = `
             .${cssClass}:not(.imhumane-responsive) .imhumane-body > * {
-                width: ${containerLength}px;
-                /* Percentage gaps are based on the parent element's width.
-                To avoid weird rendering, use px when not responsive.    */
-                gap: ${gapSize}px;
-                padding: ${gapSize}px;
+                width: ${imageSize}px;
             }
 
-            .${cssClass} .imhumane-grid {
+            .${cssClass} .imhumane-click-image {
+                position: relative;
+                cursor: crosshair;
                 background-size: contain;
                 background-image: ${challenge.imageUrl};
-                display: grid;
-                grid-template-columns: repeat(${gridLength}, 1fr);
-                grid-template-rows: repeat(${gridLength}, 1fr);
                 aspect-ratio: 1;
                 box-sizing: border-box;
             }
+
+            .${cssClass} .imhumane-click-marker {
+                position: absolute;
+                width: 1em;
+                height: 1em;
+                transform: translate(-50%, -50%);
+                border: 2px solid darkolivegreen;
+                border-radius: 50%;
+                box-sizing: border-box;
+                pointer-events: none;
+            }
         `;
     }
 
     render() {
         const root = this.container.body;
         root.appendChild(this.title);
-        root.appendChild(this.grid);
+        root.appendChild(this.image);
+        root.appendChild(this.actions);
+    }
+
+    hide() {
+        this.container.body.innerHTML = "";
+    }
+
+    readAnswer() {
+        return this.point;
+    }
+
+    reset() {
+        this.point = null;
+        this.marker.hidden = true;
+        this.button.disabled = true;
+    }
+
+    waitForAnswer() {
+        return new Promise((resolve) => {
+            this.button.addEventListener("click", () => {
+                resolve(this.readAnswer());
+            });
+        });
+    }
+}
+
+class ChallengeAudio {
+    /**
+     *
+     * @param {ChallengeContainer} container
+     * @param {Challenge} challenge
+     */
+    constructor(container, challenge) {
+        this.container = container;
+        this.challenge = challenge;
+
+        const cssClass = container.cssClass;
+
+        // Elements
+        this.title = newElement("p", "imhumane-title");
+        this.title.innerHTML = `Type the <b>${challenge.words}</b> words you hear`;
+
+        this.audio = newElement("audio", "imhumane-audio");
+        this.audio.controls = true;
+        this.audio.src = challenge.audioUrl;
+
+        this.input = newElement("input", "imhumane-audio-input");
+        this.input.type = "text";
+        this.input.autocomplete = "off";
+        this.input.setAttribute("aria-label", "Words you hear");
+        this.input.addEventListener("input", () => {
+            this.button.disabled = !this.input.value.trim();
+        });
+        // Enter submits the answer rather than the surrounding form
+        this.input.addEventListener("keydown", (event) => {
+            if (event.key !== "Enter") return;
+            event.preventDefault();
+            if (!this.button.disabled) this.button.click();
+        });
+
+        this.actions = newElement("span", "imhumane-actions");
+        this.button = newElement("button", "imhumane-action-submit");
+        this.button.innerText = "Validate";
+        this.button.type = "button";
+        this.button.disabled = true;
+        this.actions.appendChild(this.button);
+
+        // Disable button on click
+        this.button.addEventListener("click", () => {
+            this.button.disabled = true;
+        });
+
+        // Styling
+        this.cssStyle = `
+            .${cssClass} .imhumane-audio, .${cssClass} .imhumane-audio-input {
+                display: block;
+                box-sizing: border-box;
+                width: 100%;
+                margin: 0.5em 0;
+            }
+        `;
+    }
+
+    render() {
+        const root = this.container.body;
+        root.appendChild(this.title);
+        root.appendChild(this.audio);
+        root.appendChild(this.input);
+        root.appendChild(this.actions);
+    }
+
+    hide() {
+        this.container.body.innerHTML = "";
+    }
+
+    readAnswer() {
+        return this.input.value;
+    }
+
+    reset() {
+        this.input.value = "";
+        this.button.disabled = true;
+    }
+
+    waitForAnswer() {
+        return new Promise((resolve) => {
+            this.button.addEventListener("click", () => {
+                resolve(this.readAnswer());
+            });
+        });
+    }
+}
+
+class ChallengeText {
+    /**
+     *
+     * @param {ChallengeContainer} container
+     * @param {Challenge} challenge
+     */
+    constructor(container, challenge) {
+        this.container = container;
+        this.challenge = challenge;
+
+        const cssClass = container.cssClass;
+
+        // Elements
+        this.title = newElement("p", "imhumane-title");
+        this.title.innerHTML = `Select all descriptions of <br /><b>${challenge.topic}</b>`;
+
+        this.list = newElement("ul", "imhumane-text-list");
+        this.checkboxElements = challenge.descriptions.map(description => {
+            const item = document.createElement("li");
+            const label = document.createElement("label");
+            const elem = newElement("input", "imhumane-text-option");
+            elem.type = "checkbox";
+            label.appendChild(elem);
+            label.appendChild(document.createTextNode(` ${description}`));
+            item.appendChild(label);
+            this.list.appendChild(item);
+            return elem;
+        });
+
+        this.actions = newElement("span", "imhumane-actions");
+        this.button = newElement("button", "imhumane-action-submit");
+        this.button.innerText = "Validate";
+        this.button.type = "button";
+        this.actions.appendChild(this.button);
+
+        // Disable button on click
+        this.button.addEventListener("click", () => {
+            this.button.disabled = true;
+        });
+
+        // Styling
+        this.cssStyle = `
+            .${cssClass} .imhumane-text-list {
+                list-style: none;
+                margin: 0.5em 0;
+                padding: 0;
+                max-width: 24em;
+            }
+
+            .${cssClass} .imhumane-text-list li {
+                margin: 0.25em 0;
+            }
+        `;
+    }
+
+    render() {
+        const root = this.container.body;
+        root.appendChild(this.title);
+        root.appendChild(this.list);
         root.appendChild(this.actions);
     }
 
@@ -165,6 +626,23 @@ class ChallengeGrid {
     }
 }
 
+// Views rendering each kind of challenge the user has to answer, by X-Imhumane-Kind
+const CHALLENGE_VIEWS = {
+    grid: ChallengeGrid,
+    odd_one_out: ChallengeGrid,
+    rotate: ChallengeRotate,
+    click: ChallengeClick,
+    audio: ChallengeAudio,
+    text: ChallengeText,
+};
+
+// Kinds users who can't see the images may switch to, when enabled with data-audio="true"
+// or data-text="true", and the label of the switch
+const ALTERNATIVE_KINDS = {
+    audio: "Listen to an audio challenge instead",
+    text: "Read a text challenge instead",
+};
+
 class ChallengeContainer {
     /**
      *
@@ -263,6 +741,11 @@ class ChallengeContainer {
                 width: 100%;
                 height: 2em;
             }
+
+            .${this.cssClass} .imhumane-action-switch {
+                width: 100%;
+                margin-top: 0.25em;
+            }
         `;
 
         // Elements
@@ -280,9 +763,33 @@ class ChallengeContainer {
             this.tokenInput.required = true;
         }
 
+        // Kind asked for instead of the site's usual challenges, if any
+        this.alternatives = Object.keys(ALTERNATIVE_KINDS)
+            .filter(kind => this.root.dataset[kind] === "true");
+        this.kind = null;
+
         this.doneSetup = false;
     }
 
+    /**
+     * Offer to switch between the usual challenges and the alternatives under a view
+     * @returns {Promise<Object>} `{ switchTo }` with the kind to switch to (null for the
+     *     usual challenges), once the user switches, if ever
+     */
+    offerSwitch(view) {
+        return new Promise((resolve) => {
+            if (!this.alternatives.length) return;
+            for (const kind of [null, ...this.alternatives]) {
+                if (kind === this.kind) continue;
+                const button = newElement("button", "imhumane-action-switch");
+                button.type = "button";
+                button.innerText = kind ? ALTERNATIVE_KINDS[kind] : "Show an image challenge instead";
+                button.addEventListener("click", () => resolve({ switchTo: kind }));
+                view.actions.appendChild(button);
+            }
+        });
+    }
+
     setOverlayText(text) {
         this.overlay.hidden = false;
         this.overlay.innerText = text;
@@ -319,36 +826,64 @@ class ChallengeContainer {
     async runUntilComplete() {
         this.setup();
 
+        let session = null;
         while (true) {
             this.setOverlayText("Loading");
-            const challenge = await fetchChallenge();
-            const grid = new ChallengeGrid(this, challenge);
-
-            // Add the grid's CSS to the existing style element.
-            this.style.innerHTML = this.cssStyle + grid.cssStyle;
-
-            grid.render();
-            this.hideOverlay();
-            const answer = await grid.waitForAnswer();
+            const challenge = await fetchChallenge(this.root.dataset, session, this.kind);
+            let view = null;
+            let answer;
+            if (challenge.kind === "proof_of_work") {
+                // Solved in the background, without asking anything of the user
+                this.setOverlayText("Verifying");
+                answer = await challenge.solveProofOfWork();
+            } else {
+                const View = CHALLENGE_VIEWS[challenge.kind];
+                if (!View) {
+                    this.setOverlayText(`Unsupported challenge: ${challenge.kind}`);
+                    return;
+                }
+                view = new View(this, challenge);
+
+                // Add the view's CSS to the existing style element.
+                this.style.innerHTML = this.cssStyle + view.cssStyle;
+
+                view.render();
+                this.hideOverlay();
+                answer = await Promise.race([view.waitForAnswer(), this.offerSwitch(view)]);
+                if (answer instanceof Object && "switchTo" in answer) {
+                    // Fetch the other kind, carrying on with the same session
+                    this.kind = answer.switchTo;
+                    this.body.innerHTML = "";
+                    this.style.innerHTML = this.cssStyle;
+                    continue;
+                }
+            }
 
             this.setOverlayText("Validating");
             try {
                 const result = await challenge.validate(answer);
-                if (result) {
+                if (result && result.done) {
+                    // Multi-round sessions are redeemed with the session ID
+                    const token = session || challenge.challengeId;
                     this.setOverlayText("Success!");
                     this.root.classList.add("imhumane-success");
-                    this.setToken(challenge.challengeId);
-                    grid.hide();
+                    this.setToken(token);
+                    if (view) view.hide();
 
                     this.root.dispatchEvent(new CustomEvent("imhumane-success", {
-                        detail: { token: challenge.challengeId }
+                        detail: { token }
                     }));
 
                     return;
+                } else if (result) {
+                    session = result.session;
+                    this.setOverlayText(`Correct! ${result.completed} of ${result.rounds} done`);
                 } else {
+                    session = null;
                     this.setOverlayText("Failed: Incorrect selection");
                 }
             } catch (err) {
+                session = null;
                 this.setOverlayText(`Validation failed: ${err}`);
             }
 
@@ -1 +1 @@
-pub const CHALLENGE_JS: &'static [u8] = include_bytes!("challenge.js");
+pub const CHALLENGE_JS: &[u8] = include_bytes!("challenge.js");
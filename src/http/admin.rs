@@ -0,0 +1,121 @@
+use std::{sync::Arc, time::Duration};
+
+use super::{
+    error::ApiError,
+    extract::{Json, JsonOrForm},
+};
+use crate::service::ImHumane;
+use axum::{
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    Extension,
+};
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_MIME_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct AttackModePayload {
+    enabled: bool,
+    /// Seconds until attack mode relaxes by itself. It stays on until switched off when unset.
+    #[serde(default)]
+    duration: Option<u64>,
+}
+
+/// Reads the token from an `Authorization: Bearer` header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/attack-mode",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Whether attack mode is on, and why", body = AttackStatus),
+        (status = 401, description = "The bearer token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn attack_mode_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    imhumane.check_admin_token(bearer_token(&headers))?;
+    Ok(Json(imhumane.attack_status()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/attack-mode",
+    tag = "admin",
+    request_body(
+        content = AttackModePayload,
+        description = "Sent as JSON or as a URL encoded form",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 200, description = "Attack mode after the change", body = AttackStatus),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The bearer token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn attack_mode_put(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    headers: HeaderMap,
+    JsonOrForm(payload): JsonOrForm<AttackModePayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    imhumane.check_admin_token(bearer_token(&headers))?;
+    let status =
+        imhumane.set_attack_mode(payload.enabled, payload.duration.map(Duration::from_secs));
+
+    tracing::warn!(
+        enabled = payload.enabled,
+        duration = payload.duration,
+        "Attack mode set by admin"
+    );
+
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/labels",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Unlabelled images which enough answers agree on, with the collection proposed for each", body = Vec<LabelProposal>),
+        (status = 401, description = "The bearer token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn labels_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    imhumane.check_admin_token(bearer_token(&headers))?;
+    Ok(Json(imhumane.label_proposals()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "meta",
+    responses(
+        (status = 200, description = "Service metrics in the Prometheus text format", content_type = "text/plain", body = String),
+        (status = 401, description = "The bearer token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn metrics_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    // Traffic per site and the attack state are for operators only
+    imhumane.check_admin_token(bearer_token(&headers))?;
+    Ok((
+        [(header::CONTENT_TYPE, METRICS_MIME_TYPE)],
+        imhumane.render_metrics(),
+    ))
+}
@@ -3,3 +3,15 @@ pub const HEADER_TOPIC: &str = "X-Imhumane-Topic";
 pub const HEADER_GAP_SIZE: &str = "X-Imhumane-Gap-Size";
 pub const HEADER_IMAGE_SIZE: &str = "X-Imhumane-Image-Size";
 pub const HEADER_GRID_LENGTH: &str = "X-Imhumane-Grid-Length";
+pub const HEADER_ACTION: &str = "X-Imhumane-Action";
+pub const HEADER_CDATA: &str = "X-Imhumane-Cdata";
+pub const HEADER_SOLVE_TIME: &str = "X-Imhumane-Solve-Time";
+pub const HEADER_KIND: &str = "X-Imhumane-Kind";
+pub const HEADER_NONCE: &str = "X-Imhumane-Nonce";
+pub const HEADER_INTRUDERS: &str = "X-Imhumane-Intruders";
+pub const HEADER_POW_BITS: &str = "X-Imhumane-Pow-Bits";
+pub const HEADER_COLLAGE_SIZE: &str = "X-Imhumane-Collage-Size";
+pub const HEADER_TILES: &str = "X-Imhumane-Tiles";
+pub const HEADER_TILE_URLS: &str = "X-Imhumane-Tile-Urls";
+pub const HEADER_EXAMPLE_URL: &str = "X-Imhumane-Example-Url";
+pub const HEADER_WORDS: &str = "X-Imhumane-Words";
@@ -0,0 +1,262 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Query,
+    http::{request::Parts, HeaderName, HeaderValue, Method},
+};
+use snafu::prelude::*;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+
+use super::constants::{
+    HEADER_ACTION, HEADER_CDATA, HEADER_COLLAGE_SIZE, HEADER_EXAMPLE_URL, HEADER_GAP_SIZE,
+    HEADER_GRID_LENGTH, HEADER_ID, HEADER_IMAGE_SIZE, HEADER_INTRUDERS, HEADER_KIND, HEADER_NONCE,
+    HEADER_POW_BITS, HEADER_SOLVE_TIME, HEADER_TILES, HEADER_TILE_URLS, HEADER_TOPIC, HEADER_WORDS,
+};
+use crate::service::{ImHumane, Site};
+
+const WILDCARD: &str = "*";
+
+#[derive(Debug, Snafu)]
+pub enum CorsError {
+    #[snafu(display("Invalid CORS header name {name}"))]
+    InvalidHeader { name: String },
+    #[snafu(display("Invalid CORS method {method}"))]
+    InvalidMethod { method: String },
+    #[snafu(display("Exposed headers cannot be a wildcard when credentials are allowed"))]
+    WildcardExposeWithCredentials,
+}
+
+/// Cross origin policy applied to every route.
+///
+/// Sites with `allowed_origins` only accept those origins on requests naming their `sitekey`.
+/// Every other request is checked against `allowed_origins` here.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API. `*` allows any origin.
+    #[serde(default = "default_wildcard")]
+    pub allowed_origins: Vec<String>,
+
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+
+    #[serde(default = "default_exposed_headers")]
+    pub exposed_headers: Vec<String>,
+
+    #[serde(default)]
+    pub allow_credentials: bool,
+
+    /// Seconds browsers may cache preflight responses for.
+    #[serde(default)]
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    /// Environment variable keys which hold comma separated lists.
+    pub const LIST_KEYS: [&'static str; 4] = [
+        "allowed_origins",
+        "allowed_methods",
+        "allowed_headers",
+        "exposed_headers",
+    ];
+
+    pub fn layer(&self, service: Arc<ImHumane>) -> Result<CorsLayer, CorsError> {
+        let allowed_origins = self.allowed_origins.clone();
+        let allow_origin = AllowOrigin::predicate(move |origin: &HeaderValue, parts: &Parts| {
+            let Ok(origin) = origin.to_str() else {
+                return false;
+            };
+            let site = site_key(parts).and_then(|site_key| service.site(&site_key));
+            allows_origin(origin, site.as_ref(), &allowed_origins)
+        });
+
+        let allow_methods = if self.allowed_methods.iter().any(|m| m == WILDCARD) {
+            match self.allow_credentials {
+                true => AllowMethods::mirror_request(),
+                false => AllowMethods::any(),
+            }
+        } else {
+            AllowMethods::list(
+                self.allowed_methods
+                    .iter()
+                    .map(|method| {
+                        Method::from_str(&method.to_uppercase())
+                            .ok()
+                            .context(InvalidMethodSnafu { method })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        };
+
+        let allow_headers = if self.allowed_headers.iter().any(|h| h == WILDCARD) {
+            match self.allow_credentials {
+                true => AllowHeaders::mirror_request(),
+                false => AllowHeaders::any(),
+            }
+        } else {
+            AllowHeaders::list(header_names(&self.allowed_headers)?)
+        };
+
+        let expose_headers = if self.exposed_headers.iter().any(|h| h == WILDCARD) {
+            ensure!(!self.allow_credentials, WildcardExposeWithCredentialsSnafu);
+            ExposeHeaders::any()
+        } else {
+            ExposeHeaders::list(header_names(&self.exposed_headers)?)
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(allow_methods)
+            .allow_headers(allow_headers)
+            .expose_headers(expose_headers)
+            .allow_credentials(self.allow_credentials);
+
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(Duration::from_secs(max_age));
+        }
+
+        Ok(layer)
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: default_wildcard(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            exposed_headers: default_exposed_headers(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SiteKeyQuery {
+    sitekey: Option<String>,
+}
+
+/// The `sitekey` query parameter, if present.
+fn site_key(parts: &Parts) -> Option<String> {
+    Query::<SiteKeyQuery>::try_from_uri(&parts.uri)
+        .ok()?
+        .0
+        .sitekey
+}
+
+/// Whether `origin` may call the API, for the site named by the request if there is one.
+/// Sites without `allowed_origins` of their own leave it to `allowed_origins`.
+fn allows_origin(origin: &str, site: Option<&Site>, allowed_origins: &[String]) -> bool {
+    match site {
+        Some(site) if !site.allowed_origins.is_empty() => site.allows_origin(origin),
+        _ => allowed_origins.iter().any(|o| o == WILDCARD || o == origin),
+    }
+}
+
+fn header_names(names: &[String]) -> Result<Vec<HeaderName>, CorsError> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_str(name)
+                .ok()
+                .context(InvalidHeaderSnafu { name })
+        })
+        .collect()
+}
+
+fn default_wildcard() -> Vec<String> {
+    vec![WILDCARD.to_string()]
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    vec!["content-type".to_string()]
+}
+
+fn default_exposed_headers() -> Vec<String> {
+    [
+        HEADER_ID,
+        HEADER_TOPIC,
+        HEADER_GAP_SIZE,
+        HEADER_IMAGE_SIZE,
+        HEADER_GRID_LENGTH,
+        HEADER_ACTION,
+        HEADER_CDATA,
+        HEADER_SOLVE_TIME,
+        HEADER_KIND,
+        HEADER_INTRUDERS,
+        HEADER_NONCE,
+        HEADER_POW_BITS,
+        HEADER_COLLAGE_SIZE,
+        HEADER_TILES,
+        HEADER_TILE_URLS,
+        HEADER_EXAMPLE_URL,
+        HEADER_WORDS,
+    ]
+    .map(str::to_string)
+    .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn parts(uri: &str) -> Parts {
+        Request::get(uri).body(()).unwrap().into_parts().0
+    }
+
+    fn site(allowed_origins: &[&str]) -> Site {
+        serde_json::from_value(serde_json::json!({
+            "site_key": "site",
+            "secret_key": "secret",
+            "allowed_origins": allowed_origins,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn site_keys_are_percent_decoded() {
+        assert_eq!(
+            site_key(&parts("/challenge?sitekey=my%20site&kind=audio")),
+            Some("my site".to_string())
+        );
+        assert_eq!(
+            site_key(&parts("/challenge?kind=audio&sitekey=a+b")),
+            Some("a b".to_string())
+        );
+        assert_eq!(site_key(&parts("/challenge?kind=audio")), None);
+        assert_eq!(site_key(&parts("/challenge")), None);
+    }
+
+    #[test]
+    fn sites_with_origins_only_allow_those() {
+        let global = default_wildcard();
+        let site = site(&["https://a.example"]);
+
+        assert!(allows_origin("https://a.example", Some(&site), &global));
+        assert!(!allows_origin("https://b.example", Some(&site), &global));
+    }
+
+    #[test]
+    fn other_requests_use_the_global_origins() {
+        let global = vec!["https://b.example".to_string()];
+        let site = site(&[]);
+
+        assert!(allows_origin("https://b.example", Some(&site), &global));
+        assert!(!allows_origin("https://a.example", Some(&site), &global));
+        assert!(allows_origin("https://b.example", None, &global));
+        assert!(!allows_origin("https://a.example", None, &global));
+        assert!(allows_origin(
+            "https://a.example",
+            None,
+            &default_wildcard()
+        ));
+    }
+}
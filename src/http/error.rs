@@ -0,0 +1,304 @@
+use axum::{
+    extract::rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::service::{Error, Mismatch};
+
+const PROBLEM_MIME_TYPE: &str = "application/problem+json";
+
+/// Stable, machine readable error codes. Clients should match on these rather than on `detail`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    MalformedId,
+    MalformedRequest,
+    UnknownChallenge,
+    UnknownSession,
+    Expired,
+    WrongAnswer,
+    TooFast,
+    TooSlow,
+    InvalidToken,
+    UnknownSite,
+    OriginNotAllowed,
+    InvalidSecret,
+    InvalidAdminToken,
+    IpMismatch,
+    UserAgentMismatch,
+    OriginMismatch,
+    ActionMismatch,
+    BatchTooLarge,
+    RateLimited,
+    KindNotOffered,
+    NotReady,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MalformedId => "malformed_id",
+            Self::MalformedRequest => "malformed_request",
+            Self::UnknownChallenge => "unknown_challenge",
+            Self::UnknownSession => "unknown_session",
+            Self::Expired => "expired",
+            Self::WrongAnswer => "wrong_answer",
+            Self::TooFast => "too_fast",
+            Self::TooSlow => "too_slow",
+            Self::InvalidToken => "invalid_token",
+            Self::UnknownSite => "unknown_site",
+            Self::OriginNotAllowed => "origin_not_allowed",
+            Self::InvalidSecret => "invalid_secret",
+            Self::InvalidAdminToken => "invalid_admin_token",
+            Self::IpMismatch => "ip_mismatch",
+            Self::UserAgentMismatch => "user_agent_mismatch",
+            Self::OriginMismatch => "origin_mismatch",
+            Self::ActionMismatch => "action_mismatch",
+            Self::BatchTooLarge => "batch_too_large",
+            Self::RateLimited => "rate_limited",
+            Self::KindNotOffered => "kind_not_offered",
+            Self::NotReady => "not_ready",
+            Self::Internal => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MalformedId
+            | Self::MalformedRequest
+            | Self::UnknownSite
+            | Self::KindNotOffered => StatusCode::BAD_REQUEST,
+            Self::UnknownChallenge | Self::UnknownSession => StatusCode::NOT_FOUND,
+            Self::Expired | Self::TooSlow => StatusCode::GONE,
+            Self::WrongAnswer
+            | Self::TooFast
+            | Self::InvalidToken
+            | Self::InvalidSecret
+            | Self::InvalidAdminToken
+            | Self::IpMismatch
+            | Self::UserAgentMismatch
+            | Self::OriginMismatch
+            | Self::ActionMismatch => StatusCode::UNAUTHORIZED,
+            Self::OriginNotAllowed => StatusCode::FORBIDDEN,
+            Self::BatchTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::NotReady => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// An RFC 9457 problem details document.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: String,
+    status: u16,
+    detail: String,
+    code: ErrorCode,
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    code: ErrorCode,
+    detail: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.code.status();
+
+        if status.is_server_error() {
+            tracing::error!(code = self.code.as_str(), "{}", self.detail);
+        }
+
+        let problem = Problem {
+            problem_type: format!("urn:imhumane:error:{}", self.code.as_str()),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string(),
+            status: status.as_u16(),
+            detail: self.detail,
+            code: self.code,
+        };
+
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_MIME_TYPE)],
+            serde_json::to_string(&problem).unwrap(),
+        )
+            .into_response()
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let code = match err {
+            // Challenges made on the spot may run short of collections
+            Error::NotReady | Error::InsufficientCollections => ErrorCode::NotReady,
+            Error::UnknownChallenge { .. } => ErrorCode::UnknownChallenge,
+            Error::UnknownSession { .. } => ErrorCode::UnknownSession,
+            Error::ChallengeExpired { .. } => ErrorCode::Expired,
+            Error::WrongAnswer { .. } => ErrorCode::WrongAnswer,
+            Error::AnsweredTooFast { .. } => ErrorCode::TooFast,
+            Error::AnsweredTooSlow { .. } => ErrorCode::TooSlow,
+            Error::InvalidToken { .. } => ErrorCode::InvalidToken,
+            Error::UnknownSite { .. } => ErrorCode::UnknownSite,
+            Error::OriginNotAllowed { .. } => ErrorCode::OriginNotAllowed,
+            Error::InvalidSecret => ErrorCode::InvalidSecret,
+            Error::InvalidAdminToken => ErrorCode::InvalidAdminToken,
+            Error::InvalidAction { .. } | Error::InvalidCdata => ErrorCode::MalformedRequest,
+            Error::KindNotOffered { .. } => ErrorCode::KindNotOffered,
+            Error::BatchTooLarge { .. } => ErrorCode::BatchTooLarge,
+            Error::RateLimited { .. } => ErrorCode::RateLimited,
+            Error::BindingMismatch { mismatch, .. } => match mismatch {
+                Mismatch::Ip => ErrorCode::IpMismatch,
+                Mismatch::UserAgent => ErrorCode::UserAgentMismatch,
+                Mismatch::Origin => ErrorCode::OriginMismatch,
+                Mismatch::Action => ErrorCode::ActionMismatch,
+            },
+            Error::Scan { .. }
+            | Error::CollectionName { .. }
+            | Error::CollectionMetadata { .. }
+            | Error::ImageMetadata { .. }
+            | Error::ReadImage { .. }
+            | Error::ReadAudio { .. }
+            | Error::StateLock { .. }
+            | Error::NoGenerator { .. }
+            | Error::GridTooSmall { .. }
+            | Error::GenerateImage { .. }
+            | Error::GenerateAudio { .. }
+            | Error::OpenImage { .. }
+            | Error::OpenThumbnail { .. }
+            | Error::ReadSites { .. }
+            | Error::ParseSites { .. }
+            | Error::ReadLabels { .. }
+            | Error::ParseLabels { .. }
+            | Error::WriteLabels { .. }
+            | Error::DuplicateSite { .. }
+            | Error::SiteCannotGenerate { .. } => ErrorCode::Internal,
+        };
+        Self::new(code, err.to_string())
+    }
+}
+
+/// Parses an ID sent in a body or query string. Malformed IDs are rejected as `malformed_id`,
+/// as they are in paths.
+pub fn parse_id(id: &str) -> Result<uuid::Uuid, ApiError> {
+    id.parse()
+        .map_err(|err| ApiError::new(ErrorCode::MalformedId, format!("Invalid ID {id}: {err}")))
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(ErrorCode::MalformedId, rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(ErrorCode::MalformedRequest, rejection.body_text())
+    }
+}
+
+impl From<FormRejection> for ApiError {
+    fn from(rejection: FormRejection) -> Self {
+        Self::new(ErrorCode::MalformedRequest, rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(ErrorCode::MalformedRequest, rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ChallengeKind;
+
+    #[test]
+    fn codes_have_a_name_and_status() {
+        let codes = [
+            (ErrorCode::MalformedId, StatusCode::BAD_REQUEST),
+            (ErrorCode::MalformedRequest, StatusCode::BAD_REQUEST),
+            (ErrorCode::UnknownChallenge, StatusCode::NOT_FOUND),
+            (ErrorCode::UnknownSession, StatusCode::NOT_FOUND),
+            (ErrorCode::Expired, StatusCode::GONE),
+            (ErrorCode::WrongAnswer, StatusCode::UNAUTHORIZED),
+            (ErrorCode::TooFast, StatusCode::UNAUTHORIZED),
+            (ErrorCode::TooSlow, StatusCode::GONE),
+            (ErrorCode::InvalidToken, StatusCode::UNAUTHORIZED),
+            (ErrorCode::UnknownSite, StatusCode::BAD_REQUEST),
+            (ErrorCode::OriginNotAllowed, StatusCode::FORBIDDEN),
+            (ErrorCode::InvalidSecret, StatusCode::UNAUTHORIZED),
+            (ErrorCode::InvalidAdminToken, StatusCode::UNAUTHORIZED),
+            (ErrorCode::IpMismatch, StatusCode::UNAUTHORIZED),
+            (ErrorCode::UserAgentMismatch, StatusCode::UNAUTHORIZED),
+            (ErrorCode::OriginMismatch, StatusCode::UNAUTHORIZED),
+            (ErrorCode::ActionMismatch, StatusCode::UNAUTHORIZED),
+            (ErrorCode::BatchTooLarge, StatusCode::PAYLOAD_TOO_LARGE),
+            (ErrorCode::RateLimited, StatusCode::TOO_MANY_REQUESTS),
+            (ErrorCode::KindNotOffered, StatusCode::BAD_REQUEST),
+            (ErrorCode::NotReady, StatusCode::SERVICE_UNAVAILABLE),
+            (ErrorCode::Internal, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (code, status) in codes {
+            // The name in problem types must match the serialized `code`
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+            assert_eq!(code.status(), status, "{}", code.as_str());
+        }
+    }
+
+    #[test]
+    fn service_errors_map_to_codes() {
+        let code = |err: Error| ApiError::from(err).code();
+        assert_eq!(code(Error::InsufficientCollections), ErrorCode::NotReady);
+        assert_eq!(
+            code(Error::KindNotOffered {
+                kind: ChallengeKind::Audio
+            }),
+            ErrorCode::KindNotOffered
+        );
+        assert_eq!(
+            code(Error::UnknownChallenge {
+                challenge_id: "id".to_string()
+            }),
+            ErrorCode::UnknownChallenge
+        );
+        assert_eq!(code(Error::InvalidCdata), ErrorCode::MalformedRequest);
+    }
+
+    #[test]
+    fn malformed_ids_are_reported_as_such() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        assert_eq!(parse_id(id).unwrap().to_string(), id);
+        assert_eq!(
+            parse_id(&id.to_uppercase()).unwrap().to_string(),
+            id,
+            "IDs are normalised as they are in paths"
+        );
+        assert_eq!(
+            parse_id("not-an-id").unwrap_err().code(),
+            ErrorCode::MalformedId
+        );
+    }
+}
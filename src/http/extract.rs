@@ -0,0 +1,212 @@
+//! Wrappers around the axum extractors which reject with an [`ApiError`].
+
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+
+use super::error::ApiError;
+use crate::service::{ClientInfo, ImHumane};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Form), rejection(ApiError))]
+pub struct Form<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// Accepts either a JSON or a URL encoded form body, depending on the `Content-Type`.
+pub struct JsonOrForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = header_str(req.headers(), header::CONTENT_TYPE)
+            .is_some_and(|content_type| content_type.starts_with("application/json"));
+
+        if is_json {
+            let Json(payload) = Json::from_request(req, state).await?;
+            Ok(Self(payload))
+        } else {
+            let Form(payload) = Form::from_request(req, state).await?;
+            Ok(Self(payload))
+        }
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: impl header::AsHeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let trusted_proxies = parts
+            .extensions
+            .get::<Arc<ImHumane>>()
+            .map(|imhumane| imhumane.trusted_proxies())
+            .unwrap_or_default();
+
+        // Proxies may append their own header rather than extend the last one
+        let forwarded_for = parts
+            .headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(ClientInfo {
+            origin: header_str(&parts.headers, header::ORIGIN),
+            ip: client_ip(peer, &forwarded_for, trusted_proxies),
+            user_agent: header_str(&parts.headers, header::USER_AGENT),
+            accept_language: header_str(&parts.headers, header::ACCEPT_LANGUAGE),
+        })
+    }
+}
+
+/// Walks `X-Forwarded-For` back from the peer for as long as the hops are trusted proxies.
+/// Hops are only read right to left, as anything left of the nearest untrusted one may have
+/// been written by the client, and a hop which isn't an address ends the walk.
+///
+/// Connections without a peer address (such as UNIX sockets) are local, so they are trusted.
+fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: &str,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let hops = forwarded_for
+        .split(',')
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty());
+
+    let mut ip = peer;
+    for hop in hops.rev() {
+        if ip.is_some_and(|ip| !trusted_proxies.contains(&ip)) {
+            break;
+        }
+        match hop.parse() {
+            Ok(hop) => ip = Some(hop),
+            Err(_) => break,
+        }
+    }
+    ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXY: &str = "10.0.0.1";
+    const INNER_PROXY: &str = "10.0.0.2";
+    const CLIENT: &str = "1.2.3.4";
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn trusted() -> Vec<IpAddr> {
+        vec![ip(PROXY), ip(INNER_PROXY)]
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let peer = Some(ip(CLIENT));
+        assert_eq!(client_ip(peer, "5.6.7.8", &trusted()), peer);
+    }
+
+    #[test]
+    fn trusted_peer_forwards_the_client() {
+        let peer = Some(ip(PROXY));
+        let forwarded_for = format!("{CLIENT}, {INNER_PROXY}");
+        assert_eq!(
+            client_ip(peer, &forwarded_for, &trusted()),
+            Some(ip(CLIENT))
+        );
+    }
+
+    #[test]
+    fn junk_hop_left_of_the_client_is_ignored() {
+        let peer = Some(ip(PROXY));
+        let forwarded_for = format!("x, {CLIENT}");
+        assert_eq!(
+            client_ip(peer, &forwarded_for, &trusted()),
+            Some(ip(CLIENT))
+        );
+    }
+
+    #[test]
+    fn junk_hop_ends_the_walk() {
+        let peer = Some(ip(PROXY));
+        let forwarded_for = format!("{CLIENT}, x");
+        assert_eq!(client_ip(peer, &forwarded_for, &trusted()), peer);
+    }
+
+    #[test]
+    fn spoofed_hops_are_ignored() {
+        // The client claims to be forwarding for someone else
+        let peer = Some(ip(PROXY));
+        let forwarded_for = format!("5.6.7.8, {CLIENT}");
+        assert_eq!(
+            client_ip(peer, &forwarded_for, &trusted()),
+            Some(ip(CLIENT))
+        );
+    }
+
+    #[test]
+    fn all_trusted_chain_ends_at_the_first_hop() {
+        let peer = Some(ip(PROXY));
+        let forwarded_for = format!("{INNER_PROXY}, {PROXY}");
+        assert_eq!(
+            client_ip(peer, &forwarded_for, &trusted()),
+            Some(ip(INNER_PROXY))
+        );
+    }
+
+    #[test]
+    fn local_peer_is_trusted() {
+        assert_eq!(client_ip(None, CLIENT, &trusted()), Some(ip(CLIENT)));
+        assert_eq!(client_ip(None, "", &trusted()), None);
+    }
+}
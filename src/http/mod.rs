@@ -1,4 +1,11 @@
+pub mod admin;
 mod constants;
+pub mod cors;
+pub mod error;
+mod extract;
+pub mod openapi;
 mod router;
+pub mod v2;
+pub mod verify;
 
 pub use router::*;
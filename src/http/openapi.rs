@@ -0,0 +1,75 @@
+use axum::{response::IntoResponse, Json};
+use utoipa::OpenApi;
+
+use super::{admin, error, router, v2, verify};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ImHumane", description = "Anti bot form validator"),
+    paths(
+        router::challenge_get,
+        router::challenge_post,
+        router::challenge_example_get,
+        router::challenge_tile_get,
+        router::javascript_get,
+        router::challenge_token_get_query,
+        router::challenge_token_post_json,
+        router::challenge_token_post_form,
+        router::challenge_token_get,
+        verify::siteverify_post,
+        verify::batch_post,
+        verify::introspect_post,
+        verify::revoke_post,
+        v2::challenge_get,
+        v2::challenge_image_get,
+        v2::challenge_example_get,
+        v2::challenge_tile_get,
+        v2::challenge_answer_post,
+        admin::attack_mode_get,
+        admin::attack_mode_put,
+        admin::labels_get,
+        admin::metrics_get,
+        openapi_get,
+    ),
+    components(schemas(
+        crate::service::Answer,
+        crate::service::AttackStatus,
+        crate::service::AttackTrigger,
+        crate::service::ChallengeKind,
+        crate::service::Difficulty,
+        crate::service::LabelProposal,
+        crate::service::Tile,
+        error::ErrorCode,
+        error::Problem,
+        router::ChallengePostPayload,
+        router::TokenPostPayload,
+        router::NextRoundResponse,
+        v2::ImageDelivery,
+        v2::AnswerPayload,
+        v2::MediaSource,
+        v2::GridLayout,
+        v2::ProofOfWorkPuzzle,
+        v2::ChallengeResponse,
+        v2::AnswerResponse,
+        verify::VerifyToken,
+        verify::SiteVerifyPayload,
+        verify::SiteVerifyResponse,
+        verify::BatchVerifyPayload,
+        verify::BatchVerifyResponse,
+        verify::RevokePayload,
+        admin::AttackModePayload,
+    ))
+)]
+pub struct ApiDoc;
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "meta",
+    responses(
+        (status = 200, description = "This OpenAPI description", content_type = "application/json"),
+    )
+)]
+pub async fn openapi_get() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
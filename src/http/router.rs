@@ -1,215 +1,665 @@
 use std::sync::Arc;
 
+use super::admin;
 use super::constants::{
-    HEADER_GAP_SIZE, HEADER_GRID_LENGTH, HEADER_ID, HEADER_IMAGE_SIZE, HEADER_TOPIC,
+    HEADER_ACTION, HEADER_CDATA, HEADER_COLLAGE_SIZE, HEADER_EXAMPLE_URL, HEADER_GAP_SIZE,
+    HEADER_GRID_LENGTH, HEADER_ID, HEADER_IMAGE_SIZE, HEADER_INTRUDERS, HEADER_KIND, HEADER_NONCE,
+    HEADER_POW_BITS, HEADER_SOLVE_TIME, HEADER_TILES, HEADER_TILE_URLS, HEADER_TOPIC, HEADER_WORDS,
 };
+use super::error::{parse_id, ApiError, ErrorCode};
+use super::extract::{Form, Json, Path, Query};
+use super::openapi::openapi_get;
+use super::v2;
+use super::verify;
 use crate::html::CHALLENGE_JS;
-use crate::service::ImHumane;
+use crate::service::{
+    Answer, AnswerOutcome, ChallengeKind, ChallengePayload, ChallengeRequest, ClientInfo, ImHumane,
+    Layout, TokenRequest, ValidatedToken,
+};
 use axum::{
-    extract::{Json, Path, Query},
-    http::{header, StatusCode},
-    response::IntoResponse,
-    routing::{get, post},
-    Extension, Form, Router,
+    handler::Handler,
+    http::{header, Method, StatusCode},
+    response::{AppendHeaders, IntoResponse},
+    routing::{on, MethodFilter, MethodRouter},
+    Extension, Router,
 };
+use tower_http::cors::CorsLayer;
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
 pub struct ChallengePostPayload {
-    challenge_id: uuid::Uuid,
-    answer: String,
+    #[schema(format = Uuid)]
+    challenge_id: String,
+    answer: Answer,
+}
+
+/// Progress through a multi-round challenge which needs more correct answers.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct NextRoundResponse {
+    /// Session to request the next challenge with. It becomes the token once every round is done.
+    pub session: String,
+    /// Rounds answered correctly so far.
+    pub completed: u32,
+    pub rounds: u32,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams)]
 pub struct TokenPostPayload {
-    imhumane_token: uuid::Uuid,
+    #[schema(format = Uuid)]
+    #[param(format = Uuid)]
+    imhumane_token: String,
+    /// Action the token must have been issued for.
+    #[serde(default)]
+    action: Option<String>,
+}
+
+impl TokenPostPayload {
+    /// Anyone can call the `v1` token routes, so they only redeem tokens issued without a site.
+    /// Site tokens are redeemed with the site's secret, through `/v1/siteverify`.
+    fn token_request(&self, client: ClientInfo) -> TokenRequest {
+        TokenRequest {
+            site_key: None,
+            client,
+            action: self.action.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+pub struct ChallengeGetQuery {
+    /// Public key of the site requesting the challenge.
+    sitekey: Option<String>,
+    /// Action the challenge protects, such as `login`. Up to 32 of `[A-Za-z0-9_-]`.
+    action: Option<String>,
+    /// Opaque data returned on verification. Up to 255 of `[A-Za-z0-9_-]`.
+    cdata: Option<String>,
+    /// Multi-round session to continue.
+    session: Option<String>,
+    /// Send the tiles of collages at separate URLs, listed in `X-Imhumane-Tile-Urls`, instead
+    /// of the collage.
+    #[serde(default)]
+    tiles: bool,
+    /// Ask for `audio` or `text` instead of the site's usual challenges, for users who can't see
+    /// images.
+    kind: Option<ChallengeKind>,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+pub struct TokenQuery {
+    /// Action the token must have been issued for.
+    action: Option<String>,
+}
+
+/// Successful token validation, with the token's action, custom data and solve time as headers.
+fn token_response(token: ValidatedToken) -> impl IntoResponse {
+    let solve_time = token
+        .solve_time
+        .map(|duration| duration.as_millis().to_string());
+    let headers: Vec<_> = [
+        (HEADER_ACTION, token.action),
+        (HEADER_CDATA, token.cdata),
+        (HEADER_SOLVE_TIME, solve_time),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?)))
+    .collect();
+    (StatusCode::NO_CONTENT, AppendHeaders(headers))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/challenge",
+    tag = "v1",
+    request_body = ChallengePostPayload,
+    responses(
+        (status = 204, description = "The answer is correct and the challenge ID, or the session for multi-round challenges, is now a valid token"),
+        (status = 202, description = "The answer is correct, but the session needs another round", body = NextRoundResponse),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The answer is incorrect or came too fast", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The challenge is unknown or was already answered", body = Problem, content_type = "application/problem+json"),
+        (status = 410, description = "The challenge has expired or took too long to answer", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn challenge_post(
     Extension(imhumane): Extension<Arc<ImHumane>>,
+    client: ClientInfo,
     Json(payload): Json<ChallengePostPayload>,
-) -> impl IntoResponse {
-    let challenge_id_str = payload.challenge_id.to_string();
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = parse_id(&payload.challenge_id)?.to_string();
     let answer = payload.answer;
-    let result = imhumane.check_answer(challenge_id_str.clone(), answer.clone());
+    let result = imhumane.check_answer(challenge_id_str.clone(), answer.clone(), &client);
 
     tracing::info!(
         challenge_id = challenge_id_str,
-        provided_answer = answer,
-        correct = result,
+        provided_answer = %answer,
+        correct = result.is_ok(),
         "Validating challenge"
     );
 
-    (
-        (match result {
-            true => StatusCode::NO_CONTENT,
-            false => StatusCode::UNAUTHORIZED,
-        }),
-        [
-            ("Access-Control-Allow-Origin", "*"),
-            ("Access-Control-Allow-Headers", "*"),
-            ("Access-Control-Expose-Headers", "*"),
-            ("Access-Control-Allow-Method", "*"),
-        ],
-    )
+    Ok(match result? {
+        AnswerOutcome::Validated { .. } => StatusCode::NO_CONTENT.into_response(),
+        AnswerOutcome::NextRound {
+            session,
+            completed,
+            rounds,
+        } => (
+            StatusCode::ACCEPTED,
+            Json(NextRoundResponse {
+                session,
+                completed,
+                rounds,
+            }),
+        )
+            .into_response(),
+    })
+}
+
+/// Describes a collage. The grid length is only given for square grids of equal tiles.
+fn layout_headers(layout: &Layout) -> Vec<(&'static str, String)> {
+    let tiles = layout
+        .tiles
+        .iter()
+        .map(|tile| format!("{},{},{},{}", tile.x, tile.y, tile.width, tile.height))
+        .collect::<Vec<_>>()
+        .join(";");
+    let mut headers = vec![
+        (HEADER_GAP_SIZE, layout.gap_size.to_string()),
+        (HEADER_IMAGE_SIZE, layout.tile_size.to_string()),
+        (
+            HEADER_COLLAGE_SIZE,
+            format!("{}x{}", layout.width, layout.height),
+        ),
+        (HEADER_TILES, tiles),
+    ];
+    if layout.is_uniform() {
+        headers.push((HEADER_GRID_LENGTH, layout.rows.to_string()));
+    }
+    headers
 }
 
-pub async fn challenge_get(Extension(imhumane): Extension<Arc<ImHumane>>) -> impl IntoResponse {
-    let challenge = imhumane.get_challenge().await;
+#[utoipa::path(
+    get,
+    path = "/v1/challenge",
+    tag = "v1",
+    params(ChallengeGetQuery),
+    responses(
+        (status = 200, description = "Challenge image for grid, odd one out, rotate and click challenges, a WAV recording (`audio/wav`) for audio challenges, or a JSON list of descriptions to select from in answer order (`application/json`) for text challenges. Proof-of-work challenges, and collages sent as tiles, have an empty body", content_type = "image/webp", body = Vec<u8>,
+            headers(
+                ("X-Imhumane-Id" = String, description = "Challenge ID"),
+                ("X-Imhumane-Kind" = String, description = "Kind of challenge, `grid`, `odd_one_out`, `rotate`, `click`, `audio`, `text` or `proof_of_work`"),
+                ("X-Imhumane-Topic" = String, description = "Topic of the images or descriptions to select, or of the object to click"),
+                ("X-Imhumane-Example-Url" = String, description = "URL of an example image of the topic, sent instead of the topic when example prompts are on"),
+                ("X-Imhumane-Gap-Size" = u32, description = "Smallest gap between tiles in pixels"),
+                ("X-Imhumane-Image-Size" = u32, description = "Largest tile size in pixels, or the image size for rotate and click challenges"),
+                ("X-Imhumane-Grid-Length" = u32, description = "Number of tiles per row and column, for square grids of equal tiles"),
+                ("X-Imhumane-Collage-Size" = String, description = "Width and height of the collage in pixels, as `WIDTHxHEIGHT`"),
+                ("X-Imhumane-Tiles" = String, description = "Tiles of the collage in answer order, as `x,y,width,height` separated by `;`"),
+                ("X-Imhumane-Tile-Urls" = String, description = "With `tiles=true`, URLs of the collage's tiles in answer order, separated by `;`"),
+                ("X-Imhumane-Intruders" = u32, description = "Number of tiles which don't belong, for odd one out challenges"),
+                ("X-Imhumane-Words" = u32, description = "Number of words read out, for audio challenges"),
+                ("X-Imhumane-Nonce" = String, description = "Proof-of-work nonce the answer is appended to"),
+                ("X-Imhumane-Pow-Bits" = u32, description = "Leading zero bits the proof-of-work hash must have"),
+            )
+        ),
+        (status = 400, description = "Unknown site key, malformed action or cdata, or a kind which can't be asked for", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The session is unknown or has expired", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The origin is not allowed for this site", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "No challenge is ready yet", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Query(query): Query<ChallengeGetQuery>,
+    client: ClientInfo,
+) -> Result<impl IntoResponse, ApiError> {
+    let request = ChallengeRequest {
+        site_key: query.sitekey,
+        client,
+        action: query.action,
+        cdata: query.cdata,
+        session: query.session,
+        kind: query.kind,
+        expires: false,
+    };
+    let mut challenge = imhumane.get_challenge(&request).await?;
 
     tracing::info!(
         challenge_id = challenge.id,
+        kind = challenge.kind().as_str(),
         answer = challenge.answer,
         "Sending challenge"
     );
 
-    (
+    let mut headers = vec![
+        (HEADER_ID, challenge.id.clone()),
+        (HEADER_KIND, challenge.payload.kind().as_str().to_string()),
+    ];
+
+    // Collages can be sent as separate tiles instead, leaving the body empty
+    if let Some(layout) = challenge.payload.layout().filter(|_| query.tiles).cloned() {
+        let collage = challenge.payload.take_image().unwrap_or_default();
+        let tile_urls = imhumane
+            .hold_tiles(&challenge.id, collage, layout)
+            .await?
+            .iter()
+            .map(|key| format!("/v1/challenge/{}/tile/{key}", challenge.id))
+            .collect::<Vec<_>>()
+            .join(";");
+        headers.push((HEADER_TILE_URLS, tile_urls));
+    }
+
+    let media_type = challenge.payload.media_type();
+    let body = match challenge.payload {
+        ChallengePayload::Grid {
+            image,
+            topic,
+            example,
+            layout,
+        } => {
+            // The example stands in for the topic, which isn't sent with it
+            match example {
+                Some(example) => {
+                    imhumane.hold_example(&challenge.id, example);
+                    headers.push((
+                        HEADER_EXAMPLE_URL,
+                        format!("/v1/challenge/{}/example", challenge.id),
+                    ));
+                }
+                None => headers.push((HEADER_TOPIC, topic)),
+            }
+            headers.extend(layout_headers(&layout));
+            image
+        }
+        ChallengePayload::OddOneOut {
+            image,
+            layout,
+            intruders,
+        } => {
+            headers.push((HEADER_INTRUDERS, intruders.to_string()));
+            headers.extend(layout_headers(&layout));
+            image
+        }
+        ChallengePayload::Rotate { image, image_size } => {
+            headers.push((HEADER_IMAGE_SIZE, image_size.to_string()));
+            image
+        }
+        ChallengePayload::Click {
+            image,
+            topic,
+            image_size,
+        } => {
+            headers.extend([
+                (HEADER_TOPIC, topic),
+                (HEADER_IMAGE_SIZE, image_size.to_string()),
+            ]);
+            image
+        }
+        ChallengePayload::Audio { audio, words } => {
+            headers.push((HEADER_WORDS, words.to_string()));
+            audio
+        }
+        ChallengePayload::Text {
+            topic,
+            descriptions,
+        } => {
+            // Descriptions are free text, so they go in the body rather than headers
+            headers.push((HEADER_TOPIC, topic));
+            serde_json::to_vec(&descriptions).unwrap_or_default()
+        }
+        ChallengePayload::ProofOfWork(proof_of_work) => {
+            headers.extend([
+                (HEADER_NONCE, proof_of_work.nonce),
+                (HEADER_POW_BITS, proof_of_work.bits.to_string()),
+            ]);
+            Vec::new()
+        }
+    };
+
+    // Replaces the default type of a byte body rather than adding to it
+    let content_type = media_type
+        .filter(|_| !body.is_empty())
+        .map(|media_type| [(header::CONTENT_TYPE, media_type)]);
+
+    Ok((StatusCode::OK, content_type, AppendHeaders(headers), body))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/challenge/{challenge_id}/example",
+    tag = "v1",
+    params(("challenge_id" = uuid::Uuid, Path, description = "Challenge ID")),
+    responses(
+        (status = 200, description = "Example image of the topic to select", content_type = "image/webp", body = Vec<u8>),
+        (status = 400, description = "Malformed challenge ID", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The example was already fetched or the challenge has expired", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_example_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path(challenge_id): Path<uuid::Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let image = imhumane.take_example(&challenge_id_str);
+
+    tracing::debug!(
+        challenge_id = challenge_id_str,
+        found = image.is_some(),
+        "Sending challenge example"
+    );
+
+    let image = image.ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::UnknownChallenge,
+            format!("No example is held for challenge {challenge_id_str}"),
+        )
+    })?;
+
+    Ok((
         StatusCode::OK,
-        [
-            (header::CONTENT_TYPE.as_str(), "image/webp".to_string()),
-            (HEADER_ID, challenge.id),
-            (HEADER_TOPIC, challenge.topic),
-            (HEADER_GAP_SIZE, challenge.gap_size.to_string()),
-            (HEADER_IMAGE_SIZE, challenge.image_size.to_string()),
-            (HEADER_GRID_LENGTH, challenge.grid_length.to_string()),
-            ("Access-Control-Allow-Origin", "*".to_string()),
-            ("Access-Control-Allow-Headers", "*".to_string()),
-            ("Access-Control-Expose-Headers", "*".to_string()),
-            ("Access-Control-Allow-Method", "*".to_string()),
-        ],
-        challenge.image,
+        [(header::CONTENT_TYPE, "image/webp")],
+        image,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/challenge/{challenge_id}/tile/{tile}",
+    tag = "v1",
+    params(
+        ("challenge_id" = uuid::Uuid, Path, description = "Challenge ID"),
+        ("tile" = String, Path, description = "Random key of the tile, from `X-Imhumane-Tile-Urls`"),
+    ),
+    responses(
+        (status = 200, description = "One tile of the challenge collage", content_type = "image/webp", body = Vec<u8>),
+        (status = 400, description = "Malformed challenge ID", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The tile was already fetched or the challenge has expired", body = Problem, content_type = "application/problem+json"),
     )
+)]
+pub async fn challenge_tile_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path((challenge_id, tile)): Path<(uuid::Uuid, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let image = imhumane.take_tile(&challenge_id_str, &tile);
+
+    tracing::debug!(
+        challenge_id = challenge_id_str,
+        found = image.is_some(),
+        "Sending challenge tile"
+    );
+
+    let image = image.ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::UnknownChallenge,
+            format!("No tile {tile} is held for challenge {challenge_id_str}"),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "image/webp")],
+        image,
+    ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/static/challenge.js",
+    tag = "v1",
+    responses(
+        (status = 200, description = "Challenge widget script", content_type = "text/javascript", body = String),
+    )
+)]
 pub async fn javascript_get() -> impl IntoResponse {
     (
         StatusCode::OK,
-        [
-            ("Content-Type", "text/javascript"),
-            ("Access-Control-Allow-Origin", "*"),
-            ("Access-Control-Allow-Headers", "*"),
-            ("Access-Control-Expose-Headers", "*"),
-            ("Access-Control-Allow-Method", "*"),
-        ],
+        [("Content-Type", "text/javascript")],
         CHALLENGE_JS,
     )
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/tokens/validate/json",
+    tag = "v1",
+    request_body = TokenPostPayload,
+    responses(
+        (status = 204, description = "The token is valid. It is consumed by this request",
+            headers(
+                ("X-Imhumane-Action" = String, description = "Action the challenge was requested for, if any"),
+                ("X-Imhumane-Cdata" = String, description = "Custom data the challenge was requested with, if any"),
+                ("X-Imhumane-Solve-Time" = u64, description = "Milliseconds the challenge took to answer"),
+            )
+        ),
+        (status = 400, description = "Malformed token", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is invalid, was issued for a site, or was solved by a different client or for a different action", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn challenge_token_post_json(
     Extension(imhumane): Extension<Arc<ImHumane>>,
+    client: ClientInfo,
     Json(payload): Json<TokenPostPayload>,
-) -> impl IntoResponse {
-    let challenge_id_str = payload.imhumane_token.to_string();
-    let result = imhumane.check_token(challenge_id_str.clone());
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = parse_id(&payload.imhumane_token)?.to_string();
+    let result = imhumane.check_token(challenge_id_str.clone(), &payload.token_request(client));
 
     tracing::info!(
         challenge_id = challenge_id_str,
-        valid = result,
+        valid = result.is_ok(),
         method = "POST",
         content_type = "application/json",
         "Validating token"
     );
 
-    match result {
-        true => StatusCode::NO_CONTENT,
-        false => StatusCode::UNAUTHORIZED,
-    }
+    Ok(token_response(result?))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/tokens/validate/form",
+    tag = "v1",
+    request_body(content = TokenPostPayload, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 204, description = "The token is valid. It is consumed by this request",
+            headers(
+                ("X-Imhumane-Action" = String, description = "Action the challenge was requested for, if any"),
+                ("X-Imhumane-Cdata" = String, description = "Custom data the challenge was requested with, if any"),
+                ("X-Imhumane-Solve-Time" = u64, description = "Milliseconds the challenge took to answer"),
+            )
+        ),
+        (status = 400, description = "Malformed token", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is invalid, was issued for a site, or was solved by a different client or for a different action", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn challenge_token_post_form(
     Extension(imhumane): Extension<Arc<ImHumane>>,
+    client: ClientInfo,
     Form(payload): Form<TokenPostPayload>,
-) -> impl IntoResponse {
-    let challenge_id_str = payload.imhumane_token.to_string();
-    let result = imhumane.check_token(challenge_id_str.clone());
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = parse_id(&payload.imhumane_token)?.to_string();
+    let result = imhumane.check_token(challenge_id_str.clone(), &payload.token_request(client));
 
     tracing::info!(
         challenge_id = challenge_id_str,
-        valid = result,
+        valid = result.is_ok(),
         method = "POST",
         content_type = "application/x-www-form-urlencoded",
         "Validating token"
     );
 
-    match result {
-        true => StatusCode::NO_CONTENT,
-        false => StatusCode::UNAUTHORIZED,
-    }
+    Ok(token_response(result?))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/tokens/validate",
+    tag = "v1",
+    params(TokenPostPayload),
+    responses(
+        (status = 204, description = "The token is valid. It is consumed by this request",
+            headers(
+                ("X-Imhumane-Action" = String, description = "Action the challenge was requested for, if any"),
+                ("X-Imhumane-Cdata" = String, description = "Custom data the challenge was requested with, if any"),
+                ("X-Imhumane-Solve-Time" = u64, description = "Milliseconds the challenge took to answer"),
+            )
+        ),
+        (status = 400, description = "Malformed token", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is invalid, was issued for a site, or was solved by a different client or for a different action", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn challenge_token_get_query(
     Extension(imhumane): Extension<Arc<ImHumane>>,
     Query(payload): Query<TokenPostPayload>,
-) -> impl IntoResponse {
-    let challenge_id_str = payload.imhumane_token.to_string();
-    let result = imhumane.check_token(challenge_id_str.clone());
+    client: ClientInfo,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = parse_id(&payload.imhumane_token)?.to_string();
+    let result = imhumane.check_token(challenge_id_str.clone(), &payload.token_request(client));
 
     tracing::info!(
         challenge_id = challenge_id_str,
-        valid = result,
+        valid = result.is_ok(),
         method = "GET",
         "Validating token"
     );
 
-    match result {
-        true => StatusCode::NO_CONTENT,
-        false => StatusCode::UNAUTHORIZED,
-    }
+    Ok(token_response(result?))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/tokens/{challenge_id}",
+    tag = "v1",
+    params(("challenge_id" = uuid::Uuid, Path, description = "Token to validate"), TokenQuery),
+    responses(
+        (status = 204, description = "The token is valid. It is consumed by this request",
+            headers(
+                ("X-Imhumane-Action" = String, description = "Action the challenge was requested for, if any"),
+                ("X-Imhumane-Cdata" = String, description = "Custom data the challenge was requested with, if any"),
+                ("X-Imhumane-Solve-Time" = u64, description = "Milliseconds the challenge took to answer"),
+            )
+        ),
+        (status = 400, description = "Malformed token", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The token is invalid, was issued for a site, or was solved by a different client or for a different action", body = Problem, content_type = "application/problem+json"),
+    )
+)]
 pub async fn challenge_token_get(
     Extension(imhumane): Extension<Arc<ImHumane>>,
     Path(challenge_id): Path<uuid::Uuid>,
-) -> impl IntoResponse {
+    Query(query): Query<TokenQuery>,
+    client: ClientInfo,
+) -> Result<impl IntoResponse, ApiError> {
     let challenge_id_str = challenge_id.to_string();
-    let result = imhumane.check_token(challenge_id_str.clone());
+    let request = TokenRequest {
+        site_key: None,
+        client,
+        action: query.action,
+    };
+    let result = imhumane.check_token(challenge_id_str.clone(), &request);
 
     tracing::info!(
         challenge_id = challenge_id_str,
-        valid = result,
+        valid = result.is_ok(),
         method = "GET",
         "Validating token"
     );
 
-    match result {
-        true => StatusCode::NO_CONTENT,
-        false => StatusCode::UNAUTHORIZED,
-    }
+    Ok(token_response(result?))
 }
 
-pub async fn cors() -> impl IntoResponse {
-    (
-        StatusCode::NO_CONTENT,
-        [
-            ("Access-Control-Allow-Origin", "*"),
-            ("Access-Control-Allow-Headers", "*"),
-            ("Access-Control-Expose-Headers", "*"),
-            ("Access-Control-Allow-Methods", "GET, POST"),
-        ],
-    )
+/// A handler for one method on one path.
+type Route = (Method, &'static str, MethodRouter);
+
+fn route<H, T>(method: Method, path: &'static str, handler: H) -> Route
+where
+    H: Handler<T, ()>,
+    T: 'static,
+{
+    let filter = MethodFilter::try_from(method.clone()).expect("routes use standard methods");
+    (method, path, on(filter, handler))
 }
 
-pub fn get_router(service: Arc<ImHumane>) -> Router {
-    Router::new()
-        .route(
-            "/v1/challenge",
-            get(challenge_get).post(challenge_post).options(cors),
-        )
-        .route("/v1/static/challenge.js", get(javascript_get).options(cors))
-        .route(
+fn routes() -> Vec<Route> {
+    vec![
+        route(Method::GET, "/v1/challenge", challenge_get),
+        route(Method::POST, "/v1/challenge", challenge_post),
+        route(
+            Method::GET,
+            "/v1/challenge/:challenge_id/example",
+            challenge_example_get,
+        ),
+        route(
+            Method::GET,
+            "/v1/challenge/:challenge_id/tile/:tile",
+            challenge_tile_get,
+        ),
+        route(Method::GET, "/v1/static/challenge.js", javascript_get),
+        route(
+            Method::GET,
             "/v1/tokens/validate",
-            get(challenge_token_get_query).options(cors),
-        )
-        .route(
+            challenge_token_get_query,
+        ),
+        route(
+            Method::POST,
             "/v1/tokens/validate/json",
-            post(challenge_token_post_json).options(cors),
-        )
-        .route(
+            challenge_token_post_json,
+        ),
+        route(
+            Method::POST,
             "/v1/tokens/validate/form",
-            post(challenge_token_post_form).options(cors),
-        )
-        .route(
-            "/v1/tokens/:challenge_id",
-            get(challenge_token_get).options(cors),
-        )
+            challenge_token_post_form,
+        ),
+        route(Method::GET, "/v1/tokens/:challenge_id", challenge_token_get),
+        route(Method::POST, "/v1/siteverify", verify::siteverify_post),
+        route(Method::POST, "/v1/siteverify/batch", verify::batch_post),
+        route(
+            Method::POST,
+            "/v1/siteverify/introspect",
+            verify::introspect_post,
+        ),
+        route(Method::POST, "/v1/siteverify/revoke", verify::revoke_post),
+        route(Method::GET, "/v2/challenge", v2::challenge_get),
+        route(
+            Method::GET,
+            "/v2/challenge/:challenge_id/image",
+            v2::challenge_image_get,
+        ),
+        route(
+            Method::GET,
+            "/v2/challenge/:challenge_id/example",
+            v2::challenge_example_get,
+        ),
+        route(
+            Method::GET,
+            "/v2/challenge/:challenge_id/tile/:tile",
+            v2::challenge_tile_get,
+        ),
+        route(
+            Method::POST,
+            "/v2/challenge/:challenge_id/answer",
+            v2::challenge_answer_post,
+        ),
+        route(Method::GET, "/admin/attack-mode", admin::attack_mode_get),
+        route(Method::PUT, "/admin/attack-mode", admin::attack_mode_put),
+        route(Method::GET, "/admin/labels", admin::labels_get),
+        route(Method::GET, "/metrics", admin::metrics_get),
+        route(Method::GET, "/openapi.json", openapi_get),
+    ]
+}
+
+/// Methods and paths served by [`get_router`], with paths in axum syntax.
+pub fn route_methods() -> Vec<(Method, &'static str)> {
+    routes()
+        .into_iter()
+        .map(|(method, path, _)| (method, path))
+        .collect()
+}
+
+pub fn get_router(service: Arc<ImHumane>, cors: CorsLayer) -> Router {
+    routes()
+        .into_iter()
+        // Routes on the same path are merged
+        .fold(Router::new(), |router, (_, path, method_router)| {
+            router.route(path, method_router)
+        })
         .layer(Extension(service))
+        .layer(cors)
 }
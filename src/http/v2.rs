@@ -0,0 +1,488 @@
+use std::{sync::Arc, time::UNIX_EPOCH};
+
+use super::{
+    error::{ApiError, ErrorCode},
+    extract::{Json, Path, Query},
+    router::NextRoundResponse,
+};
+use crate::service::{
+    Answer, AnswerOutcome, Challenge, ChallengeKind, ChallengePayload, ChallengeRequest,
+    ClientInfo, Difficulty, ImHumane, Layout, Tile,
+};
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const IMAGE_MIME_TYPE: &str = "image/webp";
+const AUDIO_MIME_TYPE: &str = "audio/wav";
+
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDelivery {
+    /// Embed the image in the response as a data URL.
+    #[default]
+    Inline,
+    /// Return a URL the image can be fetched from once.
+    Url,
+    /// Return a URL for each tile of a collage, which can be fetched once. Other images are
+    /// embedded.
+    Tiles,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+pub struct ChallengeQuery {
+    #[serde(default)]
+    image: ImageDelivery,
+    /// Public key of the site requesting the challenge.
+    sitekey: Option<String>,
+    /// Action the challenge protects, such as `login`. Up to 32 of `[A-Za-z0-9_-]`.
+    action: Option<String>,
+    /// Opaque data returned on verification. Up to 255 of `[A-Za-z0-9_-]`.
+    cdata: Option<String>,
+    /// Multi-round session to continue.
+    session: Option<String>,
+    /// Ask for `audio` or `text` instead of the site's usual challenges, for users who can't see
+    /// images.
+    kind: Option<ChallengeKind>,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct AnswerPayload {
+    answer: Answer,
+}
+
+/// Where to get an image or recording from: embedded as a data URL, or at a URL.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaSource {
+    DataUrl(String),
+    Url(String),
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct GridLayout {
+    rows: u32,
+    cols: u32,
+    /// Largest tile size in pixels.
+    tile_size: u32,
+    /// Smallest gap between tiles in pixels.
+    gap_size: u32,
+    /// Width of the collage in pixels.
+    width: u32,
+    /// Height of the collage in pixels.
+    height: u32,
+    /// Where each tile is, in the order answers list them.
+    tiles: Vec<Tile>,
+}
+
+impl From<Layout> for GridLayout {
+    fn from(layout: Layout) -> Self {
+        Self {
+            rows: layout.rows,
+            cols: layout.cols,
+            tile_size: layout.tile_size,
+            gap_size: layout.gap_size,
+            width: layout.width,
+            height: layout.height,
+            tiles: layout.tiles,
+        }
+    }
+}
+
+/// A puzzle for the browser: find an `answer` such that SHA-256 of `nonce` followed by
+/// `answer` starts with `bits` zero bits.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct ProofOfWorkPuzzle {
+    nonce: String,
+    bits: u32,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct ChallengeResponse {
+    id: String,
+    kind: ChallengeKind,
+    prompt: String,
+    /// What to look for, for kinds which have a topic.
+    topic: Option<String>,
+    /// Layout of grid and odd one out challenges.
+    grid: Option<GridLayout>,
+    /// Number of tiles which don't belong, for odd one out challenges.
+    intruders: Option<u32>,
+    /// Width and height of the image in pixels, for rotate and click challenges.
+    image_size: Option<u32>,
+    /// Number of words read out, for audio challenges.
+    words: Option<u32>,
+    /// Descriptions to select from in answer order, for text challenges.
+    descriptions: Option<Vec<String>>,
+    /// Unix timestamp (seconds) after which answers are rejected.
+    expires_at: Option<u64>,
+    difficulty: Difficulty,
+    action: Option<String>,
+    cdata: Option<String>,
+    /// Multi-round session the challenge belongs to.
+    session: Option<String>,
+    /// Which round of the session this is, starting at 1.
+    round: u32,
+    rounds: u32,
+    /// Image of the kinds which show one, unless sent as tiles.
+    image: Option<MediaSource>,
+    /// URLs of the collage's tiles in answer order, with `image=tiles`.
+    tile_urls: Option<Vec<String>>,
+    /// Image of the topic shown instead of its name, when example prompts are on. Sent like the
+    /// image, as a URL unless the image is embedded.
+    example: Option<MediaSource>,
+    /// WAV recording of audio challenges, always embedded.
+    audio: Option<MediaSource>,
+    /// Puzzle of proof-of-work challenges.
+    proof_of_work: Option<ProofOfWorkPuzzle>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct AnswerResponse {
+    success: bool,
+    /// Token to submit with the protected form, once every round is answered.
+    token: Option<String>,
+    /// Set when the session needs another round.
+    next_round: Option<NextRoundResponse>,
+}
+
+fn data_url(media_type: &str, data: Vec<u8>) -> MediaSource {
+    MediaSource::DataUrl(format!(
+        "data:{media_type};base64,{}",
+        STANDARD.encode(data)
+    ))
+}
+
+impl ChallengeResponse {
+    fn new(challenge: Challenge, image: Option<MediaSource>) -> Self {
+        let mut response = Self {
+            id: challenge.id,
+            kind: challenge.payload.kind(),
+            prompt: String::new(),
+            topic: challenge.payload.topic().map(str::to_string),
+            grid: None,
+            intruders: None,
+            image_size: None,
+            words: None,
+            descriptions: None,
+            expires_at: challenge
+                .expires_at
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+            difficulty: challenge.difficulty,
+            action: challenge.action,
+            cdata: challenge.cdata,
+            session: challenge.session,
+            round: challenge.round,
+            rounds: challenge.rounds,
+            image,
+            tile_urls: None,
+            example: None,
+            audio: None,
+            proof_of_work: None,
+        };
+
+        match challenge.payload {
+            ChallengePayload::Grid { topic, layout, .. } => {
+                response.prompt = format!("Select all images containing {topic}");
+                response.grid = Some(layout.into());
+            }
+            ChallengePayload::OddOneOut {
+                layout, intruders, ..
+            } => {
+                response.prompt = match intruders {
+                    1 => "Select the image which doesn't belong".to_string(),
+                    _ => format!("Select the {intruders} images which don't belong"),
+                };
+                response.grid = Some(layout.into());
+                response.intruders = Some(intruders);
+            }
+            ChallengePayload::Rotate { image_size, .. } => {
+                response.prompt = "Rotate the image until it is upright".to_string();
+                response.image_size = Some(image_size);
+            }
+            ChallengePayload::Click {
+                topic, image_size, ..
+            } => {
+                response.prompt = format!("Click the {topic}");
+                response.image_size = Some(image_size);
+            }
+            ChallengePayload::Audio { audio, words } => {
+                response.prompt = format!("Type the {words} words you hear");
+                response.words = Some(words);
+                response.audio = Some(data_url(AUDIO_MIME_TYPE, audio));
+            }
+            ChallengePayload::Text {
+                topic,
+                descriptions,
+            } => {
+                response.prompt = format!("Select all descriptions of {topic}");
+                response.descriptions = Some(descriptions);
+            }
+            ChallengePayload::ProofOfWork(proof_of_work) => {
+                response.prompt = format!(
+                    "Find an answer giving a SHA-256 hash with {} leading zero bits",
+                    proof_of_work.bits
+                );
+                response.proof_of_work = Some(ProofOfWorkPuzzle {
+                    nonce: proof_of_work.nonce,
+                    bits: proof_of_work.bits,
+                });
+            }
+        }
+        response
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/challenge",
+    tag = "v2",
+    params(ChallengeQuery),
+    responses(
+        (status = 200, description = "A new challenge", body = ChallengeResponse),
+        (status = 400, description = "Malformed query, action or cdata, unknown site key, or a kind which can't be asked for", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The origin is not allowed for this site", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The session is unknown or has expired", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "No challenge is ready yet", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Query(query): Query<ChallengeQuery>,
+    client: ClientInfo,
+) -> Result<impl IntoResponse, ApiError> {
+    let request = ChallengeRequest {
+        site_key: query.sitekey,
+        client,
+        action: query.action,
+        cdata: query.cdata,
+        session: query.session,
+        kind: query.kind,
+        expires: true,
+    };
+    let mut challenge = imhumane.get_challenge(&request).await?;
+
+    tracing::info!(
+        challenge_id = challenge.id,
+        answer = challenge.answer,
+        "Sending challenge"
+    );
+
+    let layout = challenge.payload.layout().cloned();
+    let mut tile_urls = None;
+    let image = match (challenge.payload.take_image(), query.image, layout) {
+        (None, _, _) => None,
+        (Some(collage), ImageDelivery::Tiles, Some(layout)) => {
+            let keys = imhumane.hold_tiles(&challenge.id, collage, layout).await?;
+            tile_urls = Some(
+                keys.iter()
+                    .map(|key| format!("/v2/challenge/{}/tile/{key}", challenge.id))
+                    .collect(),
+            );
+            None
+        }
+        (Some(image), ImageDelivery::Url, _) => {
+            imhumane.hold_image(&challenge.id, image);
+            Some(MediaSource::Url(format!(
+                "/v2/challenge/{}/image",
+                challenge.id
+            )))
+        }
+        (Some(image), _, _) => Some(data_url(IMAGE_MIME_TYPE, image)),
+    };
+    let example = challenge
+        .payload
+        .take_example()
+        .map(|example| match query.image {
+            ImageDelivery::Inline => data_url(IMAGE_MIME_TYPE, example),
+            ImageDelivery::Url | ImageDelivery::Tiles => {
+                imhumane.hold_example(&challenge.id, example);
+                MediaSource::Url(format!("/v2/challenge/{}/example", challenge.id))
+            }
+        });
+
+    let mut response = ChallengeResponse::new(challenge, image);
+    response.tile_urls = tile_urls;
+    if example.is_some() {
+        // The example stands in for the topic
+        response.prompt = "Select all images like the example".to_string();
+        response.topic = None;
+        response.example = example;
+    }
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/challenge/{challenge_id}/image",
+    tag = "v2",
+    params(("challenge_id" = uuid::Uuid, Path, description = "Challenge ID")),
+    responses(
+        (status = 200, description = "Challenge collage image", content_type = "image/webp", body = Vec<u8>),
+        (status = 400, description = "Malformed challenge ID", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The image was already fetched or the challenge has expired", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_image_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path(challenge_id): Path<uuid::Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let image = imhumane.take_image(&challenge_id_str);
+
+    tracing::info!(
+        challenge_id = challenge_id_str,
+        found = image.is_some(),
+        "Sending challenge image"
+    );
+
+    let image = image.ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::UnknownChallenge,
+            format!("No image is held for challenge {challenge_id_str}"),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, IMAGE_MIME_TYPE)],
+        image,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/challenge/{challenge_id}/example",
+    tag = "v2",
+    params(("challenge_id" = uuid::Uuid, Path, description = "Challenge ID")),
+    responses(
+        (status = 200, description = "Example image of the topic to select", content_type = "image/webp", body = Vec<u8>),
+        (status = 400, description = "Malformed challenge ID", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The example was already fetched or the challenge has expired", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_example_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path(challenge_id): Path<uuid::Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let image = imhumane.take_example(&challenge_id_str);
+
+    tracing::debug!(
+        challenge_id = challenge_id_str,
+        found = image.is_some(),
+        "Sending challenge example"
+    );
+
+    let image = image.ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::UnknownChallenge,
+            format!("No example is held for challenge {challenge_id_str}"),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, IMAGE_MIME_TYPE)],
+        image,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v2/challenge/{challenge_id}/tile/{tile}",
+    tag = "v2",
+    params(
+        ("challenge_id" = uuid::Uuid, Path, description = "Challenge ID"),
+        ("tile" = String, Path, description = "Random key of the tile, from `tile_urls`"),
+    ),
+    responses(
+        (status = 200, description = "One tile of the challenge collage", content_type = "image/webp", body = Vec<u8>),
+        (status = 400, description = "Malformed challenge ID", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The tile was already fetched or the challenge has expired", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_tile_get(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path((challenge_id, tile)): Path<(uuid::Uuid, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let image = imhumane.take_tile(&challenge_id_str, &tile);
+
+    tracing::debug!(
+        challenge_id = challenge_id_str,
+        found = image.is_some(),
+        "Sending challenge tile"
+    );
+
+    let image = image.ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::UnknownChallenge,
+            format!("No tile {tile} is held for challenge {challenge_id_str}"),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, IMAGE_MIME_TYPE)],
+        image,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/challenge/{challenge_id}/answer",
+    tag = "v2",
+    params(("challenge_id" = uuid::Uuid, Path, description = "Challenge ID")),
+    request_body = AnswerPayload,
+    responses(
+        (status = 200, description = "The answer is correct. Multi-round sessions may need another round", body = AnswerResponse),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The answer is incorrect or came too fast", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The challenge is unknown or was already answered", body = Problem, content_type = "application/problem+json"),
+        (status = 410, description = "The challenge has expired or took too long to answer", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn challenge_answer_post(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Path(challenge_id): Path<uuid::Uuid>,
+    client: ClientInfo,
+    Json(payload): Json<AnswerPayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let challenge_id_str = challenge_id.to_string();
+    let answer = payload.answer;
+    let result = imhumane.check_answer(challenge_id_str.clone(), answer.clone(), &client);
+
+    tracing::info!(
+        challenge_id = challenge_id_str,
+        provided_answer = %answer,
+        correct = result.is_ok(),
+        "Validating challenge"
+    );
+
+    let response = match result? {
+        AnswerOutcome::Validated { token } => AnswerResponse {
+            success: true,
+            token: Some(token),
+            next_round: None,
+        },
+        AnswerOutcome::NextRound {
+            session,
+            completed,
+            rounds,
+        } => AnswerResponse {
+            success: true,
+            token: None,
+            next_round: Some(NextRoundResponse {
+                session,
+                completed,
+                rounds,
+            }),
+        },
+    };
+    Ok(Json(response))
+}
@@ -0,0 +1,321 @@
+use std::{net::IpAddr, sync::Arc, time::UNIX_EPOCH};
+
+use super::{
+    error::{ApiError, ErrorCode},
+    extract::{Json, JsonOrForm},
+};
+use crate::service::{ChallengeKind, ClientInfo, ImHumane, TokenRequest, ValidatedToken};
+use axum::{http::StatusCode, response::IntoResponse, Extension};
+
+/// A token to verify, and what the client submitted it with.
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct VerifyToken {
+    /// Token submitted by the client.
+    response: String,
+    /// IP address of the client, checked when the site binds tokens to IP addresses.
+    #[serde(default)]
+    remoteip: Option<IpAddr>,
+    /// `User-Agent` of the client, checked when the site binds tokens to user agents.
+    #[serde(default)]
+    useragent: Option<String>,
+    /// `Origin` the client submitted the form from, checked when the site binds tokens to origins.
+    #[serde(default)]
+    origin: Option<String>,
+    /// Action the token must have been issued for.
+    #[serde(default)]
+    action: Option<String>,
+}
+
+impl VerifyToken {
+    fn token_request(&self, site_key: &str) -> TokenRequest {
+        TokenRequest {
+            site_key: Some(site_key.to_string()),
+            client: ClientInfo {
+                origin: self.origin.clone(),
+                ip: self.remoteip,
+                user_agent: self.useragent.clone(),
+                ..Default::default()
+            },
+            action: self.action.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct SiteVerifyPayload {
+    /// Secret key of the site the token was issued for.
+    secret: String,
+    #[serde(flatten)]
+    token: VerifyToken,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct BatchVerifyPayload {
+    /// Secret key of the site the tokens were issued for.
+    secret: String,
+    tokens: Vec<VerifyToken>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct BatchVerifyResponse {
+    /// One result per token, in the order the tokens were given.
+    results: Vec<SiteVerifyResponse>,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct RevokePayload {
+    /// Secret key of the site the token was issued for.
+    secret: String,
+    /// Token to revoke.
+    response: String,
+}
+
+#[derive(Debug, Default, serde::Serialize, utoipa::ToSchema)]
+pub struct SiteVerifyResponse {
+    success: bool,
+    /// Unix timestamp (seconds) at which the challenge was solved.
+    challenge_ts: Option<u64>,
+    /// Milliseconds between handing the challenge out and receiving its answer.
+    solve_time_ms: Option<u64>,
+    hostname: Option<String>,
+    origin: Option<String>,
+    /// Kind of challenge the token was issued for.
+    kind: Option<ChallengeKind>,
+    /// Topic of the challenge the token was issued for, for kinds which have one.
+    topic: Option<String>,
+    /// Risk score between 0 (benign) and 1 (hostile), when available.
+    score: Option<f32>,
+    sitekey: Option<String>,
+    /// Action the challenge was requested for.
+    action: Option<String>,
+    /// Custom data the challenge was requested with.
+    cdata: Option<String>,
+    /// Times the token can still be redeemed.
+    remaining_uses: Option<u32>,
+    error_codes: Vec<ErrorCode>,
+}
+
+impl From<ValidatedToken> for SiteVerifyResponse {
+    fn from(token: ValidatedToken) -> Self {
+        Self {
+            success: true,
+            remaining_uses: Some(token.remaining_uses()),
+            solve_time_ms: token.solve_time.map(|duration| duration.as_millis() as u64),
+            challenge_ts: token
+                .validated_at
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|duration| duration.as_secs()),
+            hostname: token.hostname().map(str::to_string),
+            origin: token.origin,
+            kind: Some(token.kind),
+            topic: token.topic,
+            score: token.score,
+            sitekey: token.site_key,
+            action: token.action,
+            cdata: token.cdata,
+            error_codes: Vec::new(),
+        }
+    }
+}
+
+impl From<ErrorCode> for SiteVerifyResponse {
+    fn from(code: ErrorCode) -> Self {
+        Self {
+            error_codes: vec![code],
+            ..Default::default()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/siteverify",
+    tag = "v1",
+    request_body(
+        content = SiteVerifyPayload,
+        description = "Sent as JSON or as a URL encoded form",
+        content_type = "application/x-www-form-urlencoded"
+    ),
+    responses(
+        (status = 200, description = "Verification result. A valid token uses up one redemption", body = SiteVerifyResponse),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The secret does not belong to any site", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn siteverify_post(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    JsonOrForm(payload): JsonOrForm<SiteVerifyPayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let site = imhumane.site_for_secret(&payload.secret)?;
+    let request = payload.token.token_request(&site.site_key);
+    let result = imhumane.check_token(payload.token.response.clone(), &request);
+
+    tracing::info!(
+        challenge_id = payload.token.response,
+        site_key = site.site_key,
+        valid = result.is_ok(),
+        "Verifying token"
+    );
+
+    let response = match result {
+        Ok(token) => SiteVerifyResponse::from(token),
+        Err(err) => SiteVerifyResponse::from(ApiError::from(err).code()),
+    };
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/siteverify/batch",
+    tag = "v1",
+    request_body = BatchVerifyPayload,
+    responses(
+        (status = 200, description = "Verification results. Each valid token uses up one redemption", body = BatchVerifyResponse),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The secret does not belong to any site", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Too many tokens in one batch", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn batch_post(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    Json(payload): Json<BatchVerifyPayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let site = imhumane.site_for_secret(&payload.secret)?;
+    let tokens = payload
+        .tokens
+        .into_iter()
+        .map(|token| {
+            let request = token.token_request(&site.site_key);
+            (token.response, request)
+        })
+        .collect();
+    let results = imhumane.check_tokens(tokens)?;
+
+    tracing::info!(
+        site_key = site.site_key,
+        count = results.len(),
+        valid = results.iter().filter(|result| result.is_ok()).count(),
+        "Verifying token batch"
+    );
+
+    let results = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(token) => SiteVerifyResponse::from(token),
+            Err(err) => SiteVerifyResponse::from(ApiError::from(err).code()),
+        })
+        .collect();
+
+    Ok(Json(BatchVerifyResponse { results }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/siteverify/introspect",
+    tag = "v1",
+    request_body(
+        content = SiteVerifyPayload,
+        description = "Sent as JSON or as a URL encoded form",
+        content_type = "application/x-www-form-urlencoded"
+    ),
+    responses(
+        (status = 200, description = "Verification result. The token is not redeemed", body = SiteVerifyResponse),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The secret does not belong to any site", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn introspect_post(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    JsonOrForm(payload): JsonOrForm<SiteVerifyPayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let site = imhumane.site_for_secret(&payload.secret)?;
+    let request = payload.token.token_request(&site.site_key);
+    let result = imhumane.peek_token(payload.token.response.clone(), &request);
+
+    tracing::info!(
+        challenge_id = payload.token.response,
+        site_key = site.site_key,
+        valid = result.is_ok(),
+        "Introspecting token"
+    );
+
+    let response = match result {
+        Ok(token) => SiteVerifyResponse::from(token),
+        Err(err) => SiteVerifyResponse::from(ApiError::from(err).code()),
+    };
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/siteverify/revoke",
+    tag = "v1",
+    request_body(
+        content = RevokePayload,
+        description = "Sent as JSON or as a URL encoded form",
+        content_type = "application/x-www-form-urlencoded"
+    ),
+    responses(
+        (status = 204, description = "The token was revoked"),
+        (status = 400, description = "Malformed payload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The secret does not belong to any site, or the token is invalid", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn revoke_post(
+    Extension(imhumane): Extension<Arc<ImHumane>>,
+    JsonOrForm(payload): JsonOrForm<RevokePayload>,
+) -> Result<impl IntoResponse, ApiError> {
+    let site = imhumane.site_for_secret(&payload.secret)?;
+    let result = imhumane.revoke_token(payload.response.clone(), Some(&site.site_key));
+
+    tracing::info!(
+        challenge_id = payload.response,
+        site_key = site.site_key,
+        revoked = result.is_ok(),
+        "Revoking token"
+    );
+
+    result?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, http::Uri};
+
+    #[test]
+    fn token_fields_are_read_from_forms() {
+        let uri = Uri::from_static(
+            "/?secret=secret&response=token&remoteip=192.0.2.1&origin=https%3A%2F%2Fexample.com",
+        );
+        let Query(payload) = Query::<SiteVerifyPayload>::try_from_uri(&uri).unwrap();
+
+        assert_eq!(payload.secret, "secret");
+        assert_eq!(payload.token.response, "token");
+        assert_eq!(payload.token.remoteip, Some(IpAddr::from([192, 0, 2, 1])));
+        assert_eq!(payload.token.origin.as_deref(), Some("https://example.com"));
+        assert_eq!(payload.token.useragent, None);
+    }
+
+    #[test]
+    fn batches_take_the_same_token_fields() {
+        let payload: BatchVerifyPayload = serde_json::from_value(serde_json::json!({
+            "secret": "secret",
+            "tokens": [
+                { "response": "first", "remoteip": "192.0.2.1" },
+                { "response": "second", "action": "login" },
+            ],
+        }))
+        .unwrap();
+
+        let request = payload.tokens[0].token_request("site");
+        assert_eq!(request.site_key.as_deref(), Some("site"));
+        assert_eq!(request.client.ip, Some(IpAddr::from([192, 0, 2, 1])));
+        assert_eq!(payload.tokens[1].response, "second");
+        assert_eq!(payload.tokens[1].action.as_deref(), Some("login"));
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Traffic is measured over this window when looking for attacks.
+const TRAFFIC_WINDOW: Duration = Duration::from_secs(60);
+/// Answers needed within the window before the failure ratio can trigger attack mode.
+const MIN_ANSWERS: usize = 20;
+
+/// How attack mode is triggered and what it tightens.
+#[derive(Debug, Clone, Copy)]
+pub struct AttackPolicy {
+    /// Challenge requests per minute, over all clients, which trigger attack mode.
+    pub request_threshold: Option<usize>,
+    /// Share of wrong answers per minute which triggers attack mode.
+    pub failure_ratio: Option<f32>,
+    /// How long automatically triggered attack mode lasts after the last trigger.
+    pub cooldown: Duration,
+    /// Rounds every client must answer under attack.
+    pub rounds: u32,
+    /// Longest token lifetime under attack.
+    pub token_lifetime: Duration,
+    /// Challenge requests per IP address per minute under attack.
+    pub rate_limit: usize,
+}
+
+/// Why attack mode is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AttackTrigger {
+    Manual,
+    Traffic,
+    Failures,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Activation {
+    trigger: AttackTrigger,
+    /// When attack mode relaxes. Manual activations without a duration last until turned off.
+    until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct AttackState {
+    activation: Option<Activation>,
+    requests: VecDeque<Instant>,
+    answers: VecDeque<(Instant, bool)>,
+    transitions: u64,
+    /// Until when the thresholds can't switch attack mode back on, after an admin switched
+    /// it off.
+    held_off_until: Option<Instant>,
+}
+
+impl AttackState {
+    /// Turns attack mode on or off, logging when that changes anything.
+    fn set(&mut self, activation: Option<Activation>) {
+        match (&self.activation, &activation) {
+            (None, Some(activation)) => {
+                tracing::warn!(trigger = ?activation.trigger, "Attack mode enabled");
+                self.transitions += 1;
+            }
+            (Some(_), None) => {
+                tracing::warn!("Attack mode relaxed");
+                self.transitions += 1;
+            }
+            _ => {}
+        }
+        self.activation = activation;
+    }
+
+    /// Relaxes attack mode once its time is up.
+    fn expire(&mut self, now: Instant) {
+        let expired = self
+            .activation
+            .and_then(|activation| activation.until)
+            .is_some_and(|until| until <= now);
+        if expired {
+            self.set(None);
+        }
+    }
+
+    fn forget_before(&mut self, cutoff: Instant) {
+        while self.requests.front().is_some_and(|at| *at < cutoff) {
+            self.requests.pop_front();
+        }
+        while self.answers.front().is_some_and(|(at, _)| *at < cutoff) {
+            self.answers.pop_front();
+        }
+    }
+}
+
+/// Snapshot of the attack mode state.
+#[derive(Debug, Clone, Copy, serde::Serialize, utoipa::ToSchema)]
+pub struct AttackStatus {
+    pub active: bool,
+    pub trigger: Option<AttackTrigger>,
+    /// Seconds until attack mode relaxes, if it will by itself.
+    pub remaining: Option<u64>,
+    /// Times attack mode was switched on or off.
+    pub transitions: u64,
+}
+
+/// Tightens every policy while the service is under attack.
+#[derive(Debug)]
+pub struct AttackMode {
+    policy: AttackPolicy,
+    state: Mutex<AttackState>,
+}
+
+impl AttackMode {
+    pub fn new(policy: AttackPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(AttackState::default()),
+        }
+    }
+
+    pub fn policy(&self) -> &AttackPolicy {
+        &self.policy
+    }
+
+    pub fn is_active(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.expire(Instant::now());
+        state.activation.is_some()
+    }
+
+    pub fn status(&self) -> AttackStatus {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.expire(now);
+        AttackStatus {
+            active: state.activation.is_some(),
+            trigger: state.activation.map(|activation| activation.trigger),
+            remaining: state
+                .activation
+                .and_then(|activation| activation.until)
+                .map(|until| until.duration_since(now).as_secs()),
+            transitions: state.transitions,
+        }
+    }
+
+    /// Switches attack mode on, for `duration` or until switched off, or off. Once switched
+    /// off, the thresholds can't switch it back on for a cool-down period.
+    pub fn set(&self, enabled: bool, duration: Option<Duration>) {
+        let now = Instant::now();
+        let activation = enabled.then(|| Activation {
+            trigger: AttackTrigger::Manual,
+            until: duration.map(|duration| now + duration),
+        });
+        let mut state = self.state.lock().unwrap();
+        state.held_off_until = (!enabled).then(|| now + self.policy.cooldown);
+        state.set(activation);
+    }
+
+    pub fn record_request(&self) {
+        self.record(|state, now| state.requests.push_back(now));
+    }
+
+    pub fn record_answer(&self, correct: bool) {
+        self.record(|state, now| state.answers.push_back((now, correct)));
+    }
+
+    /// Updates the traffic figures, and switches attack mode on or extends it when they
+    /// cross a threshold.
+    fn record(&self, update: impl FnOnce(&mut AttackState, Instant)) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.forget_before(now.checked_sub(TRAFFIC_WINDOW).unwrap_or(now));
+        update(&mut state, now);
+        state.expire(now);
+
+        let trigger = if self
+            .policy
+            .request_threshold
+            .is_some_and(|threshold| state.requests.len() > threshold)
+        {
+            Some(AttackTrigger::Traffic)
+        } else if self.policy.failure_ratio.is_some_and(|ratio| {
+            let failures = state.answers.iter().filter(|(_, correct)| !correct).count();
+            state.answers.len() >= MIN_ANSWERS
+                && failures as f32 / state.answers.len() as f32 > ratio
+        }) {
+            Some(AttackTrigger::Failures)
+        } else {
+            None
+        };
+
+        let Some(trigger) = trigger else {
+            return;
+        };
+        if state.held_off_until.is_some_and(|until| until > now) {
+            return;
+        }
+        match &mut state.activation {
+            // Manual activations are left to the admin
+            Some(Activation {
+                trigger: AttackTrigger::Manual,
+                ..
+            }) => {}
+            Some(activation) => activation.until = Some(now + self.policy.cooldown),
+            None => state.set(Some(Activation {
+                trigger,
+                until: Some(now + self.policy.cooldown),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attack_mode(cooldown: Duration) -> AttackMode {
+        AttackMode::new(AttackPolicy {
+            request_threshold: Some(3),
+            failure_ratio: Some(0.5),
+            cooldown,
+            rounds: 2,
+            token_lifetime: Duration::from_secs(60),
+            rate_limit: 10,
+        })
+    }
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn traffic_over_the_threshold_triggers() {
+        let attack = attack_mode(HOUR);
+        for _ in 0..3 {
+            attack.record_request();
+        }
+        assert!(!attack.is_active());
+
+        attack.record_request();
+        let status = attack.status();
+        assert!(status.active);
+        assert_eq!(status.trigger, Some(AttackTrigger::Traffic));
+        assert!(status.remaining.is_some_and(|remaining| remaining <= 3600));
+        assert_eq!(status.transitions, 1);
+    }
+
+    #[test]
+    fn failures_trigger_once_there_are_enough_answers() {
+        let attack = attack_mode(HOUR);
+        for _ in 0..MIN_ANSWERS - 1 {
+            attack.record_answer(false);
+        }
+        assert!(!attack.is_active());
+
+        attack.record_answer(false);
+        assert_eq!(attack.status().trigger, Some(AttackTrigger::Failures));
+    }
+
+    #[test]
+    fn mostly_correct_answers_dont_trigger() {
+        let attack = attack_mode(HOUR);
+        for n in 0..MIN_ANSWERS * 2 {
+            attack.record_answer(n % 2 == 0);
+        }
+        assert!(!attack.is_active());
+    }
+
+    #[test]
+    fn attack_mode_relaxes_after_the_cooldown() {
+        let attack = attack_mode(Duration::ZERO);
+        for _ in 0..4 {
+            attack.record_request();
+        }
+
+        let status = attack.status();
+        assert!(!status.active);
+        assert_eq!(status.transitions, 2);
+    }
+
+    #[test]
+    fn manual_activations_are_left_to_the_admin() {
+        let attack = attack_mode(Duration::ZERO);
+        attack.set(true, None);
+        for _ in 0..4 {
+            attack.record_request();
+        }
+
+        let status = attack.status();
+        assert_eq!(status.trigger, Some(AttackTrigger::Manual));
+        assert_eq!(status.remaining, None);
+
+        attack.set(true, Some(Duration::ZERO));
+        assert!(!attack.is_active());
+    }
+
+    #[test]
+    fn switching_off_holds_back_the_triggers() {
+        let attack = attack_mode(HOUR);
+        for _ in 0..4 {
+            attack.record_request();
+        }
+        assert!(attack.is_active());
+
+        attack.set(false, None);
+        for _ in 0..MIN_ANSWERS {
+            attack.record_request();
+            attack.record_answer(false);
+        }
+        assert!(!attack.is_active());
+    }
+
+    #[test]
+    fn triggers_are_held_back_for_the_cooldown_only() {
+        let attack = attack_mode(Duration::ZERO);
+        attack.set(true, None);
+        attack.set(false, None);
+        for _ in 0..4 {
+            attack.record_request();
+        }
+
+        // Switched on by the traffic, and relaxed straight away
+        assert_eq!(attack.status().transitions, 4);
+    }
+}
@@ -0,0 +1,255 @@
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use rand::prelude::*;
+use snafu::prelude::*;
+use std::{io::Cursor, ops::RangeInclusive, path::Path};
+
+use super::{
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    Answer, Challenge, ChallengeKind, ChallengePayload,
+};
+
+/// Sample rate of the recordings made. Clips recorded at other rates are resampled.
+const SAMPLE_RATE: u32 = 16_000;
+/// Silence before the first word and after the last, in milliseconds.
+const PADDING_MS: u32 = 500;
+/// Silence between words, in milliseconds. Varied so the words can't be cut out by position.
+const GAP_MS: RangeInclusive<u32> = 300..=1000;
+/// Volume of each word, varied so they can't be matched against the clips as they are.
+const GAIN: RangeInclusive<f32> = 0.6..=1.0;
+
+/// Words picked at random from the audio collections, read out one after another with gaps
+/// of varying length and noise throughout. The answer is the words in order, and the expected
+/// answer records them separated by spaces.
+#[derive(Debug, Default)]
+pub struct AudioGenerator;
+
+fn samples(duration_ms: u32) -> usize {
+    (SAMPLE_RATE * duration_ms / 1000) as usize
+}
+
+/// Reads a WAV clip as mono samples between -1 and 1 at [`SAMPLE_RATE`].
+pub(crate) fn read_clip(path: &Path) -> Result<Vec<f32>, Error> {
+    let mut reader = WavReader::open(path).context(ReadAudioSnafu::from(path))?;
+    let spec = reader.spec();
+    let interleaved = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>(),
+        SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect()
+        }
+    }
+    .context(ReadAudioSnafu::from(path))?;
+
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    if spec.sample_rate == SAMPLE_RATE || mono.is_empty() {
+        return Ok(mono);
+    }
+
+    // Linear interpolation is plenty for speech
+    let ratio = spec.sample_rate as f64 / SAMPLE_RATE as f64;
+    let length = (mono.len() as f64 / ratio) as usize;
+    Ok((0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let next = mono[(index + 1).min(mono.len() - 1)];
+            let fraction = (position - index as f64) as f32;
+            mono[index] * (1.0 - fraction) + next * fraction
+        })
+        .collect())
+}
+
+/// Lowercase letters and digits only, so spacing and punctuation don't matter.
+fn normalise(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+impl ChallengeGenerator for AudioGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Audio
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        let collections: Vec<_> = context
+            .audio
+            .iter()
+            .filter(|collection| !collection.clips.is_empty())
+            .collect();
+        ensure!(!collections.is_empty(), InsufficientCollectionsSnafu);
+
+        let difficulty = context.profile.difficulty;
+        let mut track = vec![0.0; samples(PADDING_MS)];
+        let mut words = Vec::new();
+        for i in 0..difficulty.audio_length() {
+            if i > 0 {
+                track.resize(track.len() + samples(rng.gen_range(GAP_MS)), 0.0);
+            }
+            let collection = collections.choose(&mut rng).unwrap();
+            let clip = collection.clips.choose(&mut rng).unwrap();
+            tracing::trace!("Reading out {}", clip.path.display());
+            let gain = rng.gen_range(GAIN);
+            track.extend(clip.samples.iter().map(|sample| sample * gain));
+            words.push(collection.name.as_str());
+        }
+        track.resize(track.len() + samples(PADDING_MS), 0.0);
+
+        let noise = difficulty.audio_noise();
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut audio = Vec::new();
+        tracing::debug!("Generating audio");
+        let mut writer =
+            WavWriter::new(Cursor::new(&mut audio), spec).context(GenerateAudioSnafu)?;
+        for sample in track {
+            let sample = (sample + rng.gen_range(-noise..=noise)).clamp(-1.0, 1.0);
+            writer
+                .write_sample((sample * i16::MAX as f32) as i16)
+                .context(GenerateAudioSnafu)?;
+        }
+        writer.finalize().context(GenerateAudioSnafu)?;
+
+        let payload = ChallengePayload::Audio {
+            audio,
+            words: words.len() as u32,
+        };
+        Ok(Challenge::new(
+            payload,
+            words.join(" "),
+            difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        let Answer::Text(answer) = answer else {
+            return false;
+        };
+        normalise(answer) == normalise(expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{
+        collection::{AudioClip, AudioCollection},
+        Difficulty, LayoutStyle, Profile,
+    };
+    use std::path::PathBuf;
+
+    /// Writes a WAV clip to a temporary file, returning its path.
+    fn write_clip(spec: WavSpec, samples: &[i16]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}.wav", uuid::Uuid::new_v4()));
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn spec(channels: u16, sample_rate: u32) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        }
+    }
+
+    #[test]
+    fn clips_are_read_as_mono_samples_within_range() {
+        let half = 1 << 14;
+        let path = write_clip(spec(2, SAMPLE_RATE), &[half, 0, -half, -half, i16::MIN, 0]);
+        let samples = read_clip(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(samples, [0.25, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn clips_are_resampled() {
+        let path = write_clip(spec(1, SAMPLE_RATE * 2), &[0; 800]);
+        let samples = read_clip(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(samples.len(), 400);
+    }
+
+    #[test]
+    fn answers_ignore_case_spacing_and_punctuation() {
+        let check =
+            |answer: &str| AudioGenerator.check("seven cat 3", &Answer::Text(answer.into()));
+        assert!(check("seven cat 3"));
+        assert!(check("Seven, Cat, 3."));
+        assert!(check("sevencat3"));
+        assert!(!check("seven cat"));
+        assert!(!check("cat seven 3"));
+        assert!(!AudioGenerator.check("seven", &Answer::Point { x: 0.0, y: 0.0 }));
+    }
+
+    #[test]
+    fn challenges_read_out_every_word_as_wav() {
+        let clip_length = samples(100);
+        let audio = [AudioCollection {
+            name: "seven".to_string(),
+            clips: vec![AudioClip {
+                path: PathBuf::from("seven/0.wav"),
+                samples: vec![0.5; clip_length],
+            }],
+        }];
+        let profile = Profile {
+            site_key: None,
+            difficulty: Difficulty::Easy,
+        };
+        let context = GeneratorContext {
+            profile: &profile,
+            collections: &[],
+            backgrounds: &[],
+            audio: &audio,
+            unlabelled: None,
+            image_size: 8,
+            gap_size: 1,
+            grid_length: 3,
+            layout_style: LayoutStyle::default(),
+            example_prompt: false,
+            pow_bits: 0,
+        };
+
+        let challenge = AudioGenerator.generate(&context).unwrap();
+        let words = Difficulty::Easy.audio_length();
+        assert_eq!(challenge.answer, vec!["seven"; words].join(" "));
+        let ChallengePayload::Audio {
+            audio,
+            words: count,
+        } = challenge.payload
+        else {
+            panic!("Expected an audio challenge, got {:?}", challenge.payload);
+        };
+        assert_eq!(count as usize, words);
+
+        let reader = WavReader::new(Cursor::new(audio)).unwrap();
+        assert_eq!(reader.spec(), spec(1, SAMPLE_RATE));
+        let shortest =
+            2 * samples(PADDING_MS) + words * clip_length + (words - 1) * samples(*GAP_MS.start());
+        let longest = shortest + (words - 1) * samples(GAP_MS.end() - GAP_MS.start());
+        assert!((shortest..=longest).contains(&(reader.len() as usize)));
+    }
+}
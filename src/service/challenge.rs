@@ -1,22 +1,357 @@
-use std::fmt::{Display, Formatter, Result};
+use std::{
+    fmt::{Display, Formatter, Result},
+    net::IpAddr,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{Difficulty, Layout, ProofOfWork};
+
+/// What a client has to do to answer a challenge.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeKind {
+    /// Select the tiles of an image grid which show the topic.
+    #[default]
+    #[serde(alias = "image")]
+    Grid,
+    /// Select the tiles of an image grid which don't belong with the rest.
+    OddOneOut,
+    /// Turn a single image until it is upright.
+    Rotate,
+    /// Click an object placed somewhere on a background image.
+    Click,
+    /// Type the words read out in a recording.
+    Audio,
+    /// Select the descriptions of images which match the topic, from a list.
+    Text,
+    /// Compute a hash preimage in the background, without showing anything.
+    ProofOfWork,
+}
+
+impl ChallengeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Grid => "grid",
+            Self::OddOneOut => "odd_one_out",
+            Self::Rotate => "rotate",
+            Self::Click => "click",
+            Self::Audio => "audio",
+            Self::Text => "text",
+            Self::ProofOfWork => "proof_of_work",
+        }
+    }
+
+    /// Whether a person has to answer the challenge, rather than their browser.
+    pub fn is_visual(&self) -> bool {
+        !matches!(self, Self::ProofOfWork)
+    }
+
+    /// Whether clients may ask for the kind instead of what their site mixes in, for users who
+    /// can't answer image challenges.
+    pub fn is_alternative(&self) -> bool {
+        matches!(self, Self::Audio | Self::Text)
+    }
+}
+
+/// What is shown to the client, which depends on the kind of challenge.
+#[derive(Debug, Clone)]
+pub enum ChallengePayload {
+    /// A collage of tiles, some showing `topic`.
+    Grid {
+        image: Vec<u8>,
+        topic: String,
+        /// Another image of the topic, shown instead of its name.
+        example: Option<Vec<u8>>,
+        layout: Layout,
+    },
+    /// A collage where `intruders` tiles come from other collections than the rest.
+    OddOneOut {
+        image: Vec<u8>,
+        layout: Layout,
+        intruders: u32,
+    },
+    /// A single image of `image_size` pixels square, turned away from upright.
+    Rotate {
+        image: Vec<u8>,
+        image_size: u32,
+    },
+    /// A background of `image_size` pixels square with an image showing `topic` on it.
+    Click {
+        image: Vec<u8>,
+        topic: String,
+        image_size: u32,
+    },
+    /// A WAV recording reading out `words` words.
+    Audio {
+        audio: Vec<u8>,
+        words: u32,
+    },
+    /// Descriptions of images, some showing `topic`, in place of a collage.
+    Text {
+        topic: String,
+        descriptions: Vec<String>,
+    },
+    ProofOfWork(ProofOfWork),
+}
+
+impl ChallengePayload {
+    pub fn kind(&self) -> ChallengeKind {
+        match self {
+            Self::Grid { .. } => ChallengeKind::Grid,
+            Self::OddOneOut { .. } => ChallengeKind::OddOneOut,
+            Self::Rotate { .. } => ChallengeKind::Rotate,
+            Self::Click { .. } => ChallengeKind::Click,
+            Self::Audio { .. } => ChallengeKind::Audio,
+            Self::Text { .. } => ChallengeKind::Text,
+            Self::ProofOfWork(_) => ChallengeKind::ProofOfWork,
+        }
+    }
+
+    /// What the client is asked to look for, if anything.
+    pub fn topic(&self) -> Option<&str> {
+        match self {
+            Self::Grid { topic, .. } | Self::Click { topic, .. } | Self::Text { topic, .. } => {
+                Some(topic)
+            }
+            Self::OddOneOut { .. }
+            | Self::Rotate { .. }
+            | Self::Audio { .. }
+            | Self::ProofOfWork(_) => None,
+        }
+    }
+
+    /// How the tiles of a collage are laid out, for kinds which show one.
+    pub fn layout(&self) -> Option<&Layout> {
+        match self {
+            Self::Grid { layout, .. } | Self::OddOneOut { layout, .. } => Some(layout),
+            Self::Rotate { .. }
+            | Self::Click { .. }
+            | Self::Audio { .. }
+            | Self::Text { .. }
+            | Self::ProofOfWork(_) => None,
+        }
+    }
+
+    /// Media type of the image, recording or list of descriptions, for kinds which have one.
+    pub fn media_type(&self) -> Option<&'static str> {
+        match self {
+            Self::Grid { .. }
+            | Self::OddOneOut { .. }
+            | Self::Rotate { .. }
+            | Self::Click { .. } => Some("image/webp"),
+            Self::Audio { .. } => Some("audio/wav"),
+            Self::Text { .. } => Some("application/json"),
+            Self::ProofOfWork(_) => None,
+        }
+    }
+
+    /// Moves the example image out of the payload, for kinds which show one.
+    pub fn take_example(&mut self) -> Option<Vec<u8>> {
+        match self {
+            Self::Grid { example, .. } => example.take(),
+            _ => None,
+        }
+    }
+
+    /// Moves the image out of the payload, leaving it empty.
+    pub fn take_image(&mut self) -> Option<Vec<u8>> {
+        match self {
+            Self::Grid { image, .. }
+            | Self::OddOneOut { image, .. }
+            | Self::Rotate { image, .. }
+            | Self::Click { image, .. } => Some(std::mem::take(image)),
+            Self::Audio { .. } | Self::Text { .. } | Self::ProofOfWork(_) => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Challenge {
     pub id: String,
-    pub image: Vec<u8>,
-    pub topic: String,
+    pub payload: ChallengePayload,
+    /// Expected answer, in the format of the generator which made the challenge.
     pub answer: String,
-    pub image_size: u32,
-    pub gap_size: u32,
-    pub grid_length: u32,
+    pub difficulty: Difficulty,
+    /// Site the challenge was generated for, if any.
+    pub site_key: Option<String>,
+    /// Set once the challenge is handed out to a client.
+    pub issued_at: Option<SystemTime>,
+    /// Set once the challenge is handed out to a client.
+    pub expires_at: Option<SystemTime>,
+    /// Action the challenge protects, as given by the page requesting it.
+    pub action: Option<String>,
+    /// Opaque data from the page requesting the challenge.
+    pub cdata: Option<String>,
+    /// Multi-round session the challenge belongs to, keyed by the session's first challenge ID.
+    pub session: Option<String>,
+    /// Which round of its session the challenge is, starting at 1.
+    pub round: u32,
+    /// Rounds to answer correctly before a token is issued.
+    pub rounds: u32,
+    /// Images of the unlabelled pool shown ungraded, in the order of their tiles.
+    pub unlabelled: Vec<PathBuf>,
+}
+
+/// An answer as submitted. Most kinds of challenge are answered with text, such as one `0` or
+/// `1` per tile, and click challenges with a point.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(untagged)]
+pub enum Answer {
+    Text(String),
+    /// Pixels from the top left corner of the image.
+    Point {
+        x: f64,
+        y: f64,
+    },
+}
+
+impl Answer {
+    /// The point given, either as such or as `x,y` text.
+    pub fn point(&self) -> Option<(f64, f64)> {
+        match self {
+            Self::Text(text) => {
+                let (x, y) = text.split_once(',')?;
+                Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+            }
+            Self::Point { x, y } => Some((*x, *y)),
+        }
+    }
+}
+
+impl From<String> for Answer {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl Display for Answer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Point { x, y } => write!(f, "{x},{y}"),
+        }
+    }
+}
+
+/// What a correct answer leads to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnswerOutcome {
+    /// Every round has been answered and `token` can be redeemed.
+    Validated { token: String },
+    /// Another challenge must be answered in `session`.
+    NextRound {
+        session: String,
+        completed: u32,
+        rounds: u32,
+    },
+}
+
+/// Details of the client making a request.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    /// Value of the `Origin` header, if sent.
+    pub origin: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    pub accept_language: Option<String>,
+}
+
+impl ClientInfo {
+    /// Stable hash of the user agent, so tokens don't need to keep the full string. It is the
+    /// start of the SHA-256 digest, so it stays the same across builds and restarts.
+    pub fn user_agent_fingerprint(&self) -> Option<u64> {
+        let digest = Sha256::digest(self.user_agent.as_ref()?);
+        Some(u64::from_be_bytes(digest[..8].try_into().unwrap()))
+    }
+}
+
+/// Describes who is asking for a challenge.
+#[derive(Debug, Clone, Default)]
+pub struct ChallengeRequest {
+    pub site_key: Option<String>,
+    pub client: ClientInfo,
+    /// Action the challenge protects, such as `login`. Carried through to the token.
+    pub action: Option<String>,
+    /// Opaque data carried through to the token.
+    pub cdata: Option<String>,
+    /// Multi-round session to continue. Its site, action and custom data take precedence.
+    pub session: Option<String>,
+    /// Kind asked for instead of the site's mix, which must be an alternative.
+    pub kind: Option<ChallengeKind>,
+    /// Whether the challenge can only be answered for the challenge lifetime. `v1` challenges
+    /// can be answered at any time, as they always could.
+    pub expires: bool,
+}
+
+impl Challenge {
+    /// A challenge which has not been handed out yet.
+    pub fn new(
+        payload: ChallengePayload,
+        answer: String,
+        difficulty: Difficulty,
+        site_key: Option<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            payload,
+            answer,
+            difficulty,
+            site_key,
+            issued_at: None,
+            expires_at: None,
+            action: None,
+            cdata: None,
+            session: None,
+            round: 1,
+            rounds: 1,
+            unlabelled: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> ChallengeKind {
+        self.payload.kind()
+    }
 }
 
 impl Display for Challenge {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
             f,
-            "ID: {}, topic: {}, answer: {}",
-            self.id, self.topic, self.answer
+            "ID: {}, kind: {:?}, topic: {}, answer: {}",
+            self.id,
+            self.kind(),
+            self.payload.topic().unwrap_or("-"),
+            self.answer
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_agent_fingerprints_are_stable() {
+        let client = ClientInfo {
+            user_agent: Some("Firefox".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(client.user_agent_fingerprint(), Some(0x2f26_2335_95d1_65e6));
+        assert_eq!(ClientInfo::default().user_agent_fingerprint(), None);
+    }
+}
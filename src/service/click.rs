@@ -0,0 +1,128 @@
+use image::{imageops, ImageFormat};
+use rand::prelude::*;
+use snafu::prelude::*;
+use std::io::Cursor;
+
+use super::{
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    Answer, Challenge, ChallengeKind, ChallengePayload,
+};
+
+/// An image from a collection placed at random on one from the backgrounds, which must be
+/// clicked. The answer is the point clicked, and the expected answer records the target's
+/// bounding box as `x,y,width,height,tolerance`.
+#[derive(Debug, Default)]
+pub struct ClickGenerator;
+
+impl ChallengeGenerator for ClickGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Click
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        let background = context
+            .backgrounds
+            .choose(&mut rng)
+            .context(InsufficientCollectionsSnafu {})?;
+        let collection = context
+            .collections
+            .iter()
+            .filter(|collection| !collection.images.is_empty())
+            .choose(&mut rng)
+            .context(InsufficientCollectionsSnafu {})?;
+        let target = collection.images.choose(&mut rng).unwrap();
+
+        // Crop to a square, so the client only needs one size
+        let background = context.background(background)?;
+        let size = background.width().min(background.height());
+        let mut canvas = background
+            .crop_imm(
+                (background.width() - size) / 2,
+                (background.height() - size) / 2,
+                size,
+                size,
+            )
+            .to_rgba8();
+
+        tracing::trace!("Placing {} on a background", target.display());
+        let target = context.thumbnail(target)?;
+        let (width, height) = (target.width(), target.height());
+        let x = rng.gen_range(0..=size.saturating_sub(width));
+        let y = rng.gen_range(0..=size.saturating_sub(height));
+        imageops::overlay(&mut canvas, &target.to_rgba8(), x.into(), y.into());
+
+        let mut image = Vec::new();
+        tracing::debug!("Generating image");
+        canvas
+            .write_to(&mut Cursor::new(&mut image), ImageFormat::WebP)
+            .context(GenerateImageSnafu {})?;
+
+        let difficulty = context.profile.difficulty;
+        let payload = ChallengePayload::Click {
+            image,
+            topic: collection.name.clone(),
+            image_size: size,
+        };
+        Ok(Challenge::new(
+            payload,
+            format!("{x},{y},{width},{height},{}", difficulty.click_tolerance()),
+            difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        let Some((click_x, click_y)) = answer.point() else {
+            return false;
+        };
+        let Ok(bounds) = expected
+            .split(',')
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return false;
+        };
+        let [x, y, width, height, tolerance] = bounds[..] else {
+            return false;
+        };
+        (x - tolerance..=x + width + tolerance).contains(&click_x)
+            && (y - tolerance..=y + height + tolerance).contains(&click_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn click(x: f64, y: f64) -> bool {
+        ClickGenerator.check("10,20,30,40,4", &Answer::Point { x, y })
+    }
+
+    #[test]
+    fn clicks_on_the_target_pass() {
+        assert!(click(10.0, 20.0));
+        assert!(click(25.0, 40.0));
+        assert!(click(40.0, 60.0));
+    }
+
+    #[test]
+    fn clicks_within_tolerance_pass() {
+        assert!(click(6.0, 16.0));
+        assert!(click(44.0, 64.0));
+        assert!(!click(5.9, 40.0));
+        assert!(!click(44.1, 40.0));
+        assert!(!click(25.0, 15.9));
+        assert!(!click(25.0, 64.1));
+    }
+
+    #[test]
+    fn clicks_may_be_sent_as_text() {
+        let generator = ClickGenerator;
+        assert!(generator.check("10,20,30,40,0", &Answer::Text("25, 40".to_string())));
+        assert!(!generator.check("10,20,30,40,0", &Answer::Text("25".to_string())));
+        assert!(!generator.check("10,20,30,40", &Answer::Point { x: 25.0, y: 40.0 }));
+    }
+}
@@ -1,8 +1,101 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Name of the optional file next to a collection's images which holds its [`CollectionMetadata`].
+pub(crate) const METADATA_FILE: &str = "collection.json";
+
+/// Extension added to an image's file name for the file holding its [`ImageMetadata`], as in
+/// `cat.jpg.json`.
+pub(crate) const IMAGE_METADATA_EXTENSION: &str = "json";
+
+/// Name of the collection holding backgrounds for click challenges. It is never a topic.
+pub(crate) const BACKGROUNDS_COLLECTION: &str = "backgrounds";
+
+/// Name of the collection holding images yet to be sorted into collections. Its images are
+/// shown ungraded in grids, and never used as a topic.
+pub(crate) const UNLABELLED_COLLECTION: &str = "unlabelled";
+
+/// Settings for a collection, read from its `collection.json`. Every field is optional.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct CollectionMetadata {
+    /// Whether the images have an obvious upright orientation, so they can be used for rotate
+    /// challenges. Turn off for abstract images.
+    pub rotatable: bool,
+}
+
+impl Default for CollectionMetadata {
+    fn default() -> Self {
+        Self { rotatable: true }
+    }
+}
+
+/// Details of one image, read from the sidecar file next to it. Every field is optional.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ImageMetadata {
+    /// Description of the image for people who can't see it, used by text challenges.
+    pub alt: Option<String>,
+}
 
 #[derive(Clone, Debug)]
 pub struct Collection {
     // pub(crate) path: PathBuf,
     pub(crate) name: String,
     pub(crate) images: Vec<PathBuf>,
+    pub(crate) metadata: CollectionMetadata,
+    /// Descriptions of the images which have one.
+    pub(crate) alt_texts: HashMap<PathBuf, String>,
+}
+
+impl Collection {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn images(&self) -> &[PathBuf] {
+        &self.images
+    }
+
+    pub fn metadata(&self) -> &CollectionMetadata {
+        &self.metadata
+    }
+
+    pub fn alt_text(&self, image: &Path) -> Option<&str> {
+        self.alt_texts.get(image).map(String::as_str)
+    }
+}
+
+/// A recording of a word, decoded when scanned so challenges don't read it again.
+#[derive(Clone, Debug)]
+pub struct AudioClip {
+    pub(crate) path: PathBuf,
+    /// Mono samples between -1 and 1, at the rate audio challenges are recorded at.
+    pub(crate) samples: Vec<f32>,
+}
+
+impl AudioClip {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A spoken word or digit, with recordings of it read out by audio challenges.
+#[derive(Clone, Debug)]
+pub struct AudioCollection {
+    /// What the recordings say, as it should be typed.
+    pub(crate) name: String,
+    pub(crate) clips: Vec<AudioClip>,
+}
+
+impl AudioCollection {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn clips(&self) -> &[AudioClip] {
+        &self.clips
+    }
 }
@@ -1,3 +1,10 @@
+use std::{net::IpAddr, time::Duration};
+
+use super::{
+    AttackMode, AttackPolicy, ChallengeKind, LabelPolicy, LayoutStyle, ProofOfWorkPolicy,
+    RiskScorer, SolveTimeLimits, SolveTimePolicy,
+};
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Config {
     pub buffer_size: usize,
@@ -7,4 +14,220 @@ pub struct Config {
     pub gap_size: u32,
 
     pub grid_length: u32,
+
+    /// How collages arrange their tiles.
+    #[serde(default)]
+    pub layout: LayoutStyle,
+
+    /// Show an example image of the topic rather than its name.
+    #[serde(default)]
+    pub example_prompts: bool,
+
+    /// Seconds a `v2` challenge may be answered for after it is handed out, and a multi-round
+    /// session waits for its next answer. `v1` challenges don't expire.
+    #[serde(default = "default_challenge_lifetime")]
+    pub challenge_lifetime: u64,
+
+    /// Seconds a validated token can be redeemed for.
+    #[serde(default = "default_token_lifetime")]
+    pub token_lifetime: u64,
+
+    /// Fastest a challenge can be answered after it is handed out, in milliseconds.
+    #[serde(default)]
+    pub min_solve_time_ms: u64,
+
+    /// Slowest a challenge can be answered after it is handed out, in milliseconds.
+    #[serde(default)]
+    pub max_solve_time_ms: Option<u64>,
+
+    /// What to do with correct answers outside the solve time limits.
+    #[serde(default)]
+    pub solve_time_policy: SolveTimePolicy,
+
+    /// Correct answers in a row needed for a token.
+    #[serde(default = "default_rounds")]
+    pub rounds: u32,
+
+    /// Kinds of challenge mixed into the pools.
+    #[serde(default = "default_challenge_kinds")]
+    pub challenge_kinds: Vec<ChallengeKind>,
+
+    /// Leading zero bits asked of normal difficulty proof-of-work challenges.
+    #[serde(default = "default_pow_bits")]
+    pub pow_bits: u32,
+
+    /// Clients with a risk score up to this get proof-of-work challenges instead of images.
+    #[serde(default)]
+    pub pow_max_risk: Option<f32>,
+
+    /// Serve harder challenges to clients with a high risk score.
+    #[serde(default)]
+    pub adaptive_difficulty: bool,
+
+    /// Seconds of client activity the risk score is based on. Rate limits count the requests
+    /// of the last minute from it, so it must be at least 60.
+    #[serde(default = "default_risk_window")]
+    pub risk_window: u64,
+
+    /// Challenge requests from one IP address within the risk window which are fully suspicious.
+    #[serde(default = "default_risk_max_requests")]
+    pub risk_max_requests: usize,
+
+    /// Challenge requests allowed per IP address per minute. Unlimited when unset.
+    #[serde(default)]
+    pub rate_limit: Option<usize>,
+
+    /// Bearer token for the admin API. The admin API is disabled when unset.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// Challenge requests per minute, over all clients, which switch attack mode on.
+    #[serde(default)]
+    pub attack_request_threshold: Option<usize>,
+
+    /// Share of wrong answers per minute which switches attack mode on.
+    #[serde(default)]
+    pub attack_failure_ratio: Option<f32>,
+
+    /// Seconds attack mode lasts after it was last triggered.
+    #[serde(default = "default_attack_cooldown")]
+    pub attack_cooldown: u64,
+
+    /// Rounds every client must answer under attack.
+    #[serde(default = "default_attack_rounds")]
+    pub attack_rounds: u32,
+
+    /// Longest token lifetime under attack, in seconds.
+    #[serde(default = "default_attack_token_lifetime")]
+    pub attack_token_lifetime: u64,
+
+    /// Challenge requests allowed per IP address per minute under attack.
+    #[serde(default = "default_attack_rate_limit")]
+    pub attack_rate_limit: usize,
+
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Most tokens a single batch verification may check.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+
+    /// Chance of a grid showing an image from the unlabelled pool, between 0 and 1.
+    #[serde(default = "default_unlabelled_chance")]
+    pub unlabelled_chance: f64,
+
+    /// Selections of an unlabelled image under a topic needed to propose it for the topic.
+    #[serde(default = "default_label_votes")]
+    pub label_votes: u32,
+
+    /// Share of the answers showing an unlabelled image under a topic which must select it.
+    #[serde(default = "default_label_agreement")]
+    pub label_agreement: f64,
+}
+
+impl Config {
+    /// Environment variable keys which hold comma separated lists.
+    pub const LIST_KEYS: [&'static str; 2] = ["trusted_proxies", "challenge_kinds"];
+
+    pub fn solve_time_limits(&self) -> SolveTimeLimits {
+        SolveTimeLimits {
+            min: Duration::from_millis(self.min_solve_time_ms),
+            max: self.max_solve_time_ms.map(Duration::from_millis),
+            policy: self.solve_time_policy,
+        }
+    }
+
+    pub fn proof_of_work_policy(&self) -> ProofOfWorkPolicy {
+        ProofOfWorkPolicy {
+            bits: self.pow_bits,
+            max_risk: self.pow_max_risk,
+        }
+    }
+
+    pub fn attack_mode(&self) -> AttackMode {
+        AttackMode::new(AttackPolicy {
+            request_threshold: self.attack_request_threshold,
+            failure_ratio: self.attack_failure_ratio,
+            cooldown: Duration::from_secs(self.attack_cooldown),
+            rounds: self.attack_rounds,
+            token_lifetime: Duration::from_secs(self.attack_token_lifetime),
+            rate_limit: self.attack_rate_limit,
+        })
+    }
+
+    pub fn label_policy(&self) -> LabelPolicy {
+        LabelPolicy {
+            chance: self.unlabelled_chance,
+            votes: self.label_votes,
+            agreement: self.label_agreement,
+        }
+    }
+
+    pub fn risk_scorer(&self) -> RiskScorer {
+        RiskScorer::new(
+            Duration::from_secs(self.risk_window),
+            self.risk_max_requests,
+        )
+    }
+}
+
+fn default_challenge_lifetime() -> u64 {
+    300
+}
+
+fn default_token_lifetime() -> u64 {
+    600
+}
+
+fn default_rounds() -> u32 {
+    1
+}
+
+fn default_challenge_kinds() -> Vec<ChallengeKind> {
+    vec![ChallengeKind::Grid]
+}
+
+fn default_pow_bits() -> u32 {
+    16
+}
+
+fn default_risk_window() -> u64 {
+    600
+}
+
+fn default_risk_max_requests() -> usize {
+    30
+}
+
+fn default_attack_cooldown() -> u64 {
+    900
+}
+
+fn default_attack_rounds() -> u32 {
+    2
+}
+
+fn default_attack_token_lifetime() -> u64 {
+    60
+}
+
+fn default_attack_rate_limit() -> usize {
+    10
+}
+
+fn default_max_batch_size() -> usize {
+    100
+}
+
+fn default_unlabelled_chance() -> f64 {
+    0.1
+}
+
+fn default_label_votes() -> u32 {
+    5
+}
+
+fn default_label_agreement() -> f64 {
+    0.8
 }
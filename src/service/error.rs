@@ -1,8 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
 
 use image::ImageError;
 use snafu::prelude::*;
 
+use super::{ChallengeKind, Mismatch};
+
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
 pub enum Error {
@@ -13,17 +18,35 @@ pub enum Error {
     },
     #[snafu(display("Could not convert a folder name to a string: {path}"))]
     CollectionName { path: String },
+    #[snafu(display("Could not parse collection metadata in {path}: {source}"))]
+    CollectionMetadata {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Could not parse image metadata in {path}: {source}"))]
+    ImageMetadata {
+        path: String,
+        source: serde_json::Error,
+    },
     #[snafu(display("Could not read image {path}"))]
     ReadImage {
         path: String,
         source: std::io::Error,
     },
+    #[snafu(display("Could not read audio clip {path}: {source}"))]
+    ReadAudio { path: String, source: hound::Error },
     #[snafu(display("Could not lock state for key {key}"))]
     StateLock { key: String },
     #[snafu(display("Insufficient collections for a valid question"))]
     InsufficientCollections,
+    #[snafu(display("A grid of {tiles} tiles is too small for odd one out challenges"))]
+    GridTooSmall { tiles: usize },
+    #[snafu(display("No generator makes {kind:?} challenges"))]
+    NoGenerator { kind: ChallengeKind },
     #[snafu(display("Failed to generate collage image: {source}"))]
     GenerateImage { source: ImageError },
+    #[snafu(display("Failed to generate audio: {source}"))]
+    GenerateAudio { source: hound::Error },
     #[snafu(display("Failed to open image {path}"))]
     OpenImage { path: String, source: ImageError },
     #[snafu(display("Failed to open thumbnail at {}: {:#}", path.display(), source))]
@@ -31,6 +54,76 @@ pub enum Error {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[snafu(display("{kind:?} challenges can't be asked for"))]
+    KindNotOffered { kind: ChallengeKind },
+    #[snafu(display("No challenge was generated in time"))]
+    NotReady,
+    #[snafu(display("Challenge {challenge_id} does not exist or was already answered"))]
+    UnknownChallenge { challenge_id: String },
+    #[snafu(display("Challenge {challenge_id} was answered faster than a human could"))]
+    AnsweredTooFast { challenge_id: String },
+    #[snafu(display("Challenge {challenge_id} took too long to answer"))]
+    AnsweredTooSlow { challenge_id: String },
+    #[snafu(display("Session {session} is unknown or has expired"))]
+    UnknownSession { session: String },
+    #[snafu(display("Challenge {challenge_id} has expired"))]
+    ChallengeExpired { challenge_id: String },
+    #[snafu(display("Incorrect answer for challenge {challenge_id}"))]
+    WrongAnswer { challenge_id: String },
+    #[snafu(display("Token {token} is not valid"))]
+    InvalidToken { token: String },
+    #[snafu(display("Could not read sites from {path}"))]
+    ReadSites {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse sites in {path}: {source}"))]
+    ParseSites {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Could not read labels from {path}"))]
+    ReadLabels {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse labels in {path}: {source}"))]
+    ParseLabels {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Could not save labels to {path}: {source}"))]
+    WriteLabels {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Site key {site_key} is defined more than once"))]
+    DuplicateSite { site_key: String },
+    #[snafu(display("Site {site_key} can't make {kind:?} challenges: {source}"))]
+    SiteCannotGenerate {
+        site_key: String,
+        kind: ChallengeKind,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<Error>,
+    },
+    #[snafu(display("Unknown site key {site_key}"))]
+    UnknownSite { site_key: String },
+    #[snafu(display("Origin {origin} is not allowed for site {site_key}"))]
+    OriginNotAllowed { origin: String, site_key: String },
+    #[snafu(display("The site secret is not valid"))]
+    InvalidSecret,
+    #[snafu(display("Invalid action {action:?}, expected up to 32 of [A-Za-z0-9_-]"))]
+    InvalidAction { action: String },
+    #[snafu(display("Invalid cdata, expected up to 255 of [A-Za-z0-9_-]"))]
+    InvalidCdata,
+    #[snafu(display("Too many challenge requests from {ip}"))]
+    RateLimited { ip: IpAddr },
+    #[snafu(display("The admin token is missing or not valid"))]
+    InvalidAdminToken,
+    #[snafu(display("Batch of {size} tokens is larger than the limit of {limit}"))]
+    BatchTooLarge { size: usize, limit: usize },
+    #[snafu(display("Token {token} does not match the request ({mismatch:?} mismatch)"))]
+    BindingMismatch { token: String, mismatch: Mismatch },
 }
 
 impl From<&Path> for ScanSnafu<String> {
@@ -49,6 +142,70 @@ impl From<&Path> for CollectionNameSnafu<String> {
     }
 }
 
+impl From<&Path> for CollectionMetadataSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ImageMetadataSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ReadAudioSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ReadSitesSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ParseSitesSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ReadLabelsSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for ParseLabelsSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
+impl From<&Path> for WriteLabelsSnafu<String> {
+    fn from(value: &Path) -> Self {
+        Self {
+            path: value.display().to_string(),
+        }
+    }
+}
+
 impl From<&PathBuf> for OpenImageSnafu<String> {
     fn from(value: &PathBuf) -> Self {
         Self {
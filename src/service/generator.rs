@@ -0,0 +1,70 @@
+use image::DynamicImage;
+use std::{fmt::Debug, path::PathBuf};
+
+use super::{
+    collection::{AudioCollection, Collection},
+    error::Error,
+    thumbnail::load_thumbnail,
+    Answer, Challenge, ChallengeKind, Layout, LayoutStyle, Profile,
+};
+
+/// What a generator has to work with to make one challenge.
+#[derive(Debug)]
+pub struct GeneratorContext<'a> {
+    pub profile: &'a Profile,
+    /// Collections the profile's site may use.
+    pub collections: &'a [Collection],
+    /// Images of the backgrounds collection.
+    pub backgrounds: &'a [PathBuf],
+    /// Words audio challenges can read out.
+    pub audio: &'a [AudioCollection],
+    /// Image of the unlabelled pool to show ungraded, when this challenge should have one.
+    pub unlabelled: Option<&'a PathBuf>,
+    /// Size of an image tile, in pixels.
+    pub image_size: u32,
+    /// Gap between image tiles, in pixels.
+    pub gap_size: u32,
+    /// Tiles per row and column at normal difficulty.
+    pub grid_length: u32,
+    /// How collages arrange their tiles.
+    pub layout_style: LayoutStyle,
+    /// Show an example image of the topic rather than its name.
+    pub example_prompt: bool,
+    /// Leading zero bits asked of proof-of-work at the profile's difficulty.
+    pub pow_bits: u32,
+}
+
+impl GeneratorContext<'_> {
+    /// Loads an image resized to the tile size.
+    pub fn thumbnail(&self, path: &PathBuf) -> Result<DynamicImage, Error> {
+        load_thumbnail(path, self.image_size)
+    }
+
+    /// Lays out a collage for the profile's difficulty.
+    pub fn layout(&self) -> Layout {
+        self.layout_style.layout(
+            self.profile.difficulty.grid_length(self.grid_length),
+            self.image_size,
+            self.gap_size,
+        )
+    }
+
+    /// Loads a background resized to fit a collage at normal difficulty.
+    pub fn background(&self, path: &PathBuf) -> Result<DynamicImage, Error> {
+        let size = self.grid_length * (self.image_size + self.gap_size) + self.gap_size;
+        load_thumbnail(path, size)
+    }
+}
+
+/// Makes one kind of challenge, and checks the answers to it.
+pub trait ChallengeGenerator: Debug + Send + Sync {
+    fn kind(&self) -> ChallengeKind;
+
+    /// Makes a challenge, with the payload shown to the client and the expected answer.
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error>;
+
+    /// Checks an answer against the expected answer of a challenge this generator made.
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        matches!(answer, Answer::Text(answer) if answer == expected)
+    }
+}
@@ -0,0 +1,411 @@
+use image::{imageops::FilterType, GenericImage, ImageFormat, Rgba, RgbaImage};
+use rand::prelude::*;
+use snafu::prelude::*;
+use std::{io::Cursor, path::PathBuf};
+
+use super::{
+    collection::Collection,
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    labels::UNGRADED_TILE,
+    Answer, Challenge, ChallengeKind, ChallengePayload, Difficulty, Layout,
+};
+
+/// Collages of tiles from a few collections. The tiles showing the topic must be selected,
+/// and the answer is one `0` or `1` per tile. A tile may show an image of the unlabelled pool
+/// instead, marked `?` in the expected answer, which is recorded rather than graded.
+#[derive(Debug, Default)]
+pub struct GridGenerator;
+
+/// Collages of tiles from one collection, except for a few intruders from others which must
+/// be selected. The answer is in the same format as [`GridGenerator`]'s.
+#[derive(Debug, Default)]
+pub struct OddOneOutGenerator;
+
+/// Draws `images` into the tiles of `layout`, in order.
+fn generate_image<'a>(
+    context: &GeneratorContext,
+    images: impl IntoIterator<Item = &'a PathBuf>,
+    layout: &Layout,
+) -> Result<Vec<u8>, Error> {
+    let mut imgbuf = RgbaImage::from_pixel(layout.width, layout.height, Rgba([0u8, 0u8, 0u8, 0u8]));
+
+    for (tile, img) in layout.tiles.iter().zip(images) {
+        tracing::trace!("Inserting {}", img.display());
+        let mut test_img = context.thumbnail(img)?;
+        // Free-form tiles can be smaller than the thumbnails
+        if test_img.width() > tile.width || test_img.height() > tile.height {
+            test_img = test_img.resize(tile.width, tile.height, FilterType::Triangle);
+        }
+        imgbuf
+            .copy_from(&test_img.to_rgba8(), tile.x, tile.y)
+            .context(GenerateImageSnafu {})?;
+    }
+
+    let mut data = Vec::new();
+
+    let mut outbuf = Cursor::new(&mut data);
+    tracing::debug!("Generating image");
+    imgbuf
+        .write_to(&mut outbuf, ImageFormat::WebP)
+        .context(GenerateImageSnafu {})?;
+
+    Ok(data)
+}
+
+/// Draws a single image to show what the tiles to select look like.
+fn generate_example(context: &GeneratorContext, img: &PathBuf) -> Result<Vec<u8>, Error> {
+    tracing::trace!("Using {} as the example", img.display());
+    let mut data = Vec::new();
+    context
+        .thumbnail(img)?
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::WebP)
+        .context(GenerateImageSnafu {})?;
+    Ok(data)
+}
+
+/// Cuts the tiles of `layout` out of a collage made for it, each as its own image.
+pub fn split_tiles(collage: &[u8], layout: &Layout) -> Result<Vec<Vec<u8>>, Error> {
+    let collage = image::load_from_memory_with_format(collage, ImageFormat::WebP)
+        .context(GenerateImageSnafu {})?;
+    layout
+        .tiles
+        .iter()
+        .map(|tile| {
+            let mut data = Vec::new();
+            collage
+                .crop_imm(tile.x, tile.y, tile.width, tile.height)
+                .write_to(&mut Cursor::new(&mut data), ImageFormat::WebP)
+                .context(GenerateImageSnafu {})?;
+            Ok(data)
+        })
+        .collect()
+}
+
+/// Images picked for a grid, in the order they are shown.
+pub(crate) struct GridSample<'a> {
+    /// Collection whose images are to be selected.
+    pub topic: &'a Collection,
+    /// Image of the topic kept out of `images`, when one was asked for.
+    pub example: Option<&'a PathBuf>,
+    /// Each image, with whether it shows the topic.
+    pub images: Vec<(&'a PathBuf, bool)>,
+}
+
+/// Picks a topic and `count` images from a few collections, weighted towards the topic.
+pub(crate) fn sample_grid<'a>(
+    rng: &mut impl Rng,
+    collections: &'a [Collection],
+    difficulty: Difficulty,
+    count: usize,
+    with_example: bool,
+) -> Result<GridSample<'a>, Error> {
+    if collections.len() < 2 {
+        return (InsufficientCollectionsSnafu {}).fail();
+    }
+
+    let range = difficulty.collections();
+    let max_collections = std::cmp::min(collections.len(), *range.end());
+    let min_collections = std::cmp::min(*range.start(), max_collections);
+    let num_collections = rng.gen_range(min_collections..=max_collections);
+
+    let mut sample = collections.choose_multiple(rng, num_collections);
+
+    // The first entry of the sample will be our "correct" collection
+    let correct = sample.next().context(InsufficientCollectionsSnafu {})?;
+
+    // The example is kept out of the grid, so it can't simply be matched
+    let example = match with_example {
+        true => {
+            ensure!(correct.images.len() > 1, InsufficientCollectionsSnafu {});
+            correct.images.choose(rng)
+        }
+        false => None,
+    };
+
+    // Weight correct answers with (num_collections)
+    let mut images: Vec<_> = correct
+        .images
+        .iter()
+        .filter(|img| Some(*img) != example)
+        .map(|img| (img, num_collections as u32))
+        .collect();
+
+    // Weight incorrect answers with 1
+    for collection in sample {
+        collection
+            .images
+            .iter()
+            .for_each(|img| images.push((img, 1)));
+    }
+
+    let images = images
+        .choose_multiple_weighted(rng, count, |(_, v)| *v)
+        .unwrap()
+        .map(|(img, weight)| (*img, *weight == num_collections as u32))
+        .collect();
+
+    Ok(GridSample {
+        topic: correct,
+        example,
+        images,
+    })
+}
+
+/// Picks how many intruders a grid of `tiles` gets. Small grids get fewer than `difficulty`
+/// asks for, so the intruders stay the odd ones out.
+fn pick_intruders(
+    rng: &mut impl Rng,
+    difficulty: Difficulty,
+    tiles: usize,
+) -> Result<usize, Error> {
+    let intruders = rng
+        .gen_range(difficulty.intruders())
+        .min(tiles.saturating_sub(1) / 2);
+    ensure!(intruders > 0, GridTooSmallSnafu { tiles });
+    Ok(intruders)
+}
+
+/// Picks `tiles` images from one collection, except for `intruders` from the others, with
+/// whether each is an intruder, in the order they are shown.
+fn sample_odd_one_out<'a>(
+    rng: &mut impl Rng,
+    collections: &'a [Collection],
+    tiles: usize,
+    intruders: usize,
+) -> Result<Vec<(&'a PathBuf, bool)>, Error> {
+    let majority_tiles = tiles
+        .checked_sub(intruders)
+        .context(GridTooSmallSnafu { tiles })?;
+
+    // The rest of the grid comes from one collection, which needs enough images for it
+    let majority = collections
+        .iter()
+        .filter(|collection| collection.images.len() >= majority_tiles)
+        .choose(rng)
+        .context(InsufficientCollectionsSnafu {})?;
+
+    let others: Vec<_> = collections
+        .iter()
+        .filter(|collection| collection.name != majority.name)
+        .flat_map(|collection| collection.images.iter())
+        .collect();
+    ensure!(others.len() >= intruders, InsufficientCollectionsSnafu {});
+
+    let mut images: Vec<_> = majority
+        .images
+        .choose_multiple(rng, majority_tiles)
+        .map(|img| (img, false))
+        .chain(
+            others
+                .choose_multiple(rng, intruders)
+                .map(|img| (*img, true)),
+        )
+        .collect();
+    images.shuffle(rng);
+    Ok(images)
+}
+
+/// One `1` for each selected tile and one `0` for each other tile.
+pub(crate) fn answer_bits(selected: impl IntoIterator<Item = bool>) -> String {
+    selected
+        .into_iter()
+        .map(|selected| if selected { '1' } else { '0' })
+        .collect()
+}
+
+impl ChallengeGenerator for GridGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Grid
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        let difficulty = context.profile.difficulty;
+        let layout = context.layout();
+        let mut sample = sample_grid(
+            &mut rng,
+            context.collections,
+            difficulty,
+            layout.tiles.len(),
+            context.example_prompt,
+        )?;
+
+        let mut answer = answer_bits(sample.images.iter().map(|(_, correct)| *correct));
+
+        // The unlabelled image takes the place of a tile from another collection
+        let mut unlabelled = Vec::new();
+        if let Some(img) = context.unlabelled {
+            let others: Vec<_> = (0..sample.images.len())
+                .filter(|i| !sample.images[*i].1)
+                .collect();
+            if let Some(&i) = others.choose(&mut rng) {
+                sample.images[i].0 = img;
+                answer.replace_range(i..i + 1, &UNGRADED_TILE.to_string());
+                unlabelled.push(img.clone());
+            }
+        }
+
+        let payload = ChallengePayload::Grid {
+            image: generate_image(context, sample.images.iter().map(|(img, _)| *img), &layout)?,
+            topic: sample.topic.name.clone(),
+            example: sample
+                .example
+                .map(|img| generate_example(context, img))
+                .transpose()?,
+            layout,
+        };
+        let mut challenge = Challenge::new(
+            payload,
+            answer,
+            difficulty,
+            context.profile.site_key.clone(),
+        );
+        challenge.unlabelled = unlabelled;
+        Ok(challenge)
+    }
+
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        let Answer::Text(answer) = answer else {
+            return false;
+        };
+        // Ungraded tiles may be selected or not
+        expected.len() == answer.len()
+            && expected
+                .chars()
+                .zip(answer.chars())
+                .all(|(expected, answer)| match expected {
+                    UNGRADED_TILE => matches!(answer, '0' | '1'),
+                    _ => expected == answer,
+                })
+    }
+}
+
+impl ChallengeGenerator for OddOneOutGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::OddOneOut
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        let difficulty = context.profile.difficulty;
+        let layout = context.layout();
+        let intruders = pick_intruders(&mut rng, difficulty, layout.tiles.len())?;
+        let question_images =
+            sample_odd_one_out(&mut rng, context.collections, layout.tiles.len(), intruders)?;
+
+        let answer = answer_bits(question_images.iter().map(|(_, intruder)| *intruder));
+
+        let payload = ChallengePayload::OddOneOut {
+            image: generate_image(
+                context,
+                question_images.iter().map(|(img, _)| *img),
+                &layout,
+            )?,
+            layout,
+            intruders: intruders as u32,
+        };
+        Ok(Challenge::new(
+            payload,
+            answer,
+            difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::service::LayoutStyle;
+
+    fn collection(name: &str, images: usize) -> Collection {
+        Collection {
+            name: name.to_string(),
+            images: (0..images)
+                .map(|i| PathBuf::from(format!("{name}/{i}.png")))
+                .collect(),
+            metadata: Default::default(),
+            alt_texts: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn answer_bits_mark_selected_tiles() {
+        assert_eq!(answer_bits([true, false, false, true]), "1001");
+        assert_eq!(answer_bits([]), "");
+    }
+
+    #[test]
+    fn intruders_stay_a_minority() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let intruders = pick_intruders(&mut rng, Difficulty::Hard, 16).unwrap();
+            assert!(Difficulty::Hard.intruders().contains(&intruders));
+            assert_eq!(pick_intruders(&mut rng, Difficulty::Hard, 4).unwrap(), 1);
+            assert_eq!(pick_intruders(&mut rng, Difficulty::Hard, 3).unwrap(), 1);
+        }
+        for tiles in 0..=2 {
+            assert!(matches!(
+                pick_intruders(&mut rng, Difficulty::Easy, tiles),
+                Err(Error::GridTooSmall { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn intruders_are_marked_in_the_answer() {
+        let collections = [
+            collection("cats", 9),
+            collection("dogs", 2),
+            collection("birds", 2),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let images = sample_odd_one_out(&mut rng, &collections, 9, 3).unwrap();
+            let answer = answer_bits(images.iter().map(|(_, intruder)| *intruder));
+            assert_eq!(answer.len(), 9);
+            assert_eq!(answer.matches('1').count(), 3);
+            // Only cats has enough images for the rest of the grid
+            for (img, intruder) in images {
+                assert_eq!(img.starts_with("cats"), !intruder);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_one_out_needs_enough_images() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let too_few = [collection("cats", 7), collection("dogs", 7)];
+        assert!(sample_odd_one_out(&mut rng, &too_few, 9, 1).is_err());
+        let no_intruders = [collection("cats", 9)];
+        assert!(sample_odd_one_out(&mut rng, &no_intruders, 9, 1).is_err());
+        assert!(matches!(
+            sample_odd_one_out(&mut rng, &no_intruders, 2, 3),
+            Err(Error::GridTooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn grid_answers_have_a_bit_per_tile() {
+        let collections = [collection("cats", 20), collection("dogs", 20)];
+        let mut rng = StdRng::seed_from_u64(0);
+        for style in [LayoutStyle::Grid, LayoutStyle::FreeForm] {
+            let layout = style.layout(3, 20, 2);
+            let sample = sample_grid(
+                &mut rng,
+                &collections,
+                Difficulty::Normal,
+                layout.tiles.len(),
+                false,
+            )
+            .unwrap();
+            let answer = answer_bits(sample.images.iter().map(|(_, correct)| *correct));
+            assert_eq!(answer.len(), layout.tiles.len());
+        }
+    }
+}
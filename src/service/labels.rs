@@ -0,0 +1,296 @@
+use snafu::prelude::*;
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Once,
+    },
+    thread,
+    time::Duration,
+};
+
+use super::{error::*, locked_file::LockedFile};
+
+/// Marks a tile in an expected answer which may be selected or not.
+pub(crate) const UNGRADED_TILE: char = '?';
+
+/// How often changed tallies are saved, so answers don't wait on the disk.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+type Tallies = BTreeMap<PathBuf, BTreeMap<String, Tally>>;
+
+/// How often unlabelled images are mixed into grids, and how many answers must agree before
+/// one is proposed for a collection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelPolicy {
+    /// Chance of a grid showing an unlabelled image, between 0 and 1.
+    pub chance: f64,
+    /// Selections of an image under a topic needed to propose it for the topic's collection.
+    pub votes: u32,
+    /// Share of the answers showing an image under a topic which must have selected it.
+    pub agreement: f64,
+}
+
+/// How correct answers treated an unlabelled image shown under one topic.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct Tally {
+    shown: u32,
+    selected: u32,
+}
+
+/// An unlabelled image which enough answers agree shows a collection's topic.
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct LabelProposal {
+    /// Path of the image.
+    pub image: String,
+    /// Collection to move the image to.
+    pub collection: String,
+    /// Answers which selected the image under the collection's topic.
+    pub selected: u32,
+    /// Answers which were shown the image under the collection's topic.
+    pub shown: u32,
+}
+
+/// Selections of unlabelled images, by image and topic, shared with the thread saving them.
+#[derive(Debug, Default)]
+struct State {
+    tallies: Mutex<Tallies>,
+    /// Set by every change, and cleared when the tallies are saved.
+    changed: AtomicBool,
+}
+
+impl State {
+    fn keep_saving(&self, path: &Path) {
+        loop {
+            thread::sleep(SAVE_INTERVAL);
+            if !self.changed.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            // Serialised under the lock, but written without it
+            let data = serde_json::to_vec(&*self.tallies.lock().unwrap());
+            if let Err(err) = save(path, data) {
+                tracing::error!("{}", err);
+                self.changed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Selections of unlabelled images, by image and topic, optionally kept in a file.
+#[derive(Debug)]
+pub struct Labels {
+    policy: LabelPolicy,
+    state: Arc<State>,
+    saving: Once,
+}
+
+impl Labels {
+    pub fn new(policy: LabelPolicy) -> Self {
+        Self {
+            policy,
+            state: Arc::default(),
+            saving: Once::new(),
+        }
+    }
+
+    pub fn policy(&self) -> LabelPolicy {
+        self.policy
+    }
+
+    /// Loads the tallies from a JSON file, which is created when missing, forgetting images
+    /// which are gone. Changes are saved to the first file loaded in the background.
+    pub fn load(&self, path: &Path) -> Result<(), Error> {
+        let locked_file = LockedFile::open_rw_no_truncate(path.to_path_buf())
+            .context(ReadLabelsSnafu::from(path))?;
+        let mut data = Vec::new();
+        (&locked_file.file)
+            .read_to_end(&mut data)
+            .context(ReadLabelsSnafu::from(path))?;
+        let tallies = match data.is_empty() {
+            true => BTreeMap::new(),
+            false => serde_json::from_slice(&data).context(ParseLabelsSnafu::from(path))?,
+        };
+
+        *self.state.tallies.lock().unwrap() = tallies;
+        self.forget_missing();
+        self.saving.call_once(|| {
+            let state = self.state.clone();
+            let path = path.to_path_buf();
+            thread::spawn(move || state.keep_saving(&path));
+        });
+        Ok(())
+    }
+
+    /// Records whether a correct answer selected each unlabelled image shown under `topic`.
+    pub fn record<'a>(
+        &self,
+        topic: &str,
+        selections: impl IntoIterator<Item = (&'a PathBuf, bool)>,
+    ) {
+        let mut tallies = self.state.tallies.lock().unwrap();
+        for (image, selected) in selections {
+            let tally = tallies
+                .entry(image.clone())
+                .or_default()
+                .entry(topic.to_string())
+                .or_default();
+            tally.shown += 1;
+            tally.selected += u32::from(selected);
+        }
+        self.state.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Forgets images which no longer exist, such as those moved to a collection.
+    pub fn forget_missing(&self) {
+        let mut tallies = self.state.tallies.lock().unwrap();
+        let count = tallies.len();
+        tallies.retain(|image, _| image.is_file());
+        if tallies.len() < count {
+            self.state.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The images answers don't agree on a topic for yet, which still need showing.
+    pub fn unsettled<'a>(&self, images: &'a [PathBuf]) -> Vec<&'a PathBuf> {
+        let tallies = self.state.tallies.lock().unwrap();
+        images
+            .iter()
+            .filter(|image| {
+                tallies
+                    .get(*image)
+                    .is_none_or(|topics| !topics.values().any(|tally| self.agrees(tally)))
+            })
+            .collect()
+    }
+
+    /// Images answers agree on, with the collection each is proposed for.
+    pub fn proposals(&self) -> Vec<LabelProposal> {
+        self.state
+            .tallies
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(image, topics)| {
+                topics
+                    .iter()
+                    .filter(|(_, tally)| self.agrees(tally))
+                    .map(|(topic, tally)| LabelProposal {
+                        image: image.display().to_string(),
+                        collection: topic.clone(),
+                        selected: tally.selected,
+                        shown: tally.shown,
+                    })
+            })
+            .collect()
+    }
+
+    fn agrees(&self, tally: &Tally) -> bool {
+        tally.selected >= self.policy.votes.max(1)
+            && tally.selected as f64 >= tally.shown as f64 * self.policy.agreement
+    }
+}
+
+fn save(path: &Path, data: serde_json::Result<Vec<u8>>) -> Result<(), Error> {
+    let data = data
+        .map_err(std::io::Error::from)
+        .context(WriteLabelsSnafu::from(path))?;
+    let locked_file = LockedFile::open_rw_no_truncate(path.to_path_buf())
+        .context(WriteLabelsSnafu::from(path))?;
+    let mut file = &locked_file.file;
+    file.set_len(0).context(WriteLabelsSnafu::from(path))?;
+    file.seek(std::io::SeekFrom::Start(0))
+        .context(WriteLabelsSnafu::from(path))?;
+    file.write_all(&data).context(WriteLabelsSnafu::from(path))
+}
+
+/// Whether the answer selected each ungraded tile, marked `?` in the expected answer.
+pub(crate) fn ungraded_selections(expected: &str, answer: &str) -> Vec<bool> {
+    expected
+        .chars()
+        .zip(answer.chars())
+        .filter(|(expected, _)| *expected == UNGRADED_TILE)
+        .map(|(_, answer)| answer == '1')
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> Labels {
+        Labels::new(LabelPolicy {
+            chance: 1.0,
+            votes: 2,
+            agreement: 0.8,
+        })
+    }
+
+    /// A file which exists, standing in for an image.
+    fn existing_image() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")
+    }
+
+    #[test]
+    fn settled_images_are_not_shown() {
+        let labels = labels();
+        let settled = PathBuf::from("settled.png");
+        let unsettled = PathBuf::from("unsettled.png");
+        for _ in 0..2 {
+            labels.record("cats", [(&settled, true), (&unsettled, false)]);
+        }
+
+        let images = [settled, unsettled.clone()];
+        assert_eq!(labels.unsettled(&images), vec![&unsettled]);
+    }
+
+    #[test]
+    fn missing_images_are_forgotten() {
+        let labels = labels();
+        let existing = existing_image();
+        let missing = PathBuf::from("moved-to-a-collection.png");
+        for _ in 0..2 {
+            labels.record("cats", [(&existing, true), (&missing, true)]);
+        }
+
+        labels.forget_missing();
+        let proposals = labels.proposals();
+        assert_eq!(proposals.len(), 1);
+        assert_eq!(proposals[0].image, existing.display().to_string());
+    }
+
+    #[test]
+    fn ungraded_tiles_are_picked_out() {
+        assert_eq!(ungraded_selections("10?0?", "10101"), vec![true, true]);
+        assert_eq!(ungraded_selections("?1?", "010"), vec![false, false]);
+        assert_eq!(ungraded_selections("101", "101"), Vec::<bool>::new());
+        // A short answer only covers the tiles it reaches
+        assert_eq!(ungraded_selections("?1?", "1"), vec![true]);
+    }
+
+    #[test]
+    fn agreement_needs_votes_and_share() {
+        let labels = labels();
+        let agrees = |selected, shown| labels.agrees(&Tally { shown, selected });
+        assert!(agrees(2, 2));
+        assert!(agrees(4, 5));
+        assert!(!agrees(1, 1));
+        assert!(!agrees(3, 4));
+        assert!(!agrees(0, 0));
+
+        let unanimous = Labels::new(LabelPolicy {
+            chance: 1.0,
+            votes: 0,
+            agreement: 1.0,
+        });
+        assert!(unanimous.agrees(&Tally {
+            shown: 1,
+            selected: 1
+        }));
+        assert!(!unanimous.agrees(&Tally {
+            shown: 0,
+            selected: 0
+        }));
+    }
+}
@@ -0,0 +1,176 @@
+use rand::prelude::*;
+
+/// Where a tile sits in a collage, in pixels from its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How tiles are arranged in a collage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutStyle {
+    /// A square grid of equal tiles with equal gaps.
+    #[default]
+    Grid,
+    /// A grid of varying width, with tiles of varying size jittered within their cells, so the
+    /// tiles can't be cut out along fixed lines.
+    FreeForm,
+}
+
+/// The tiles of a collage, in the order answers list them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    pub rows: u32,
+    pub cols: u32,
+    /// Largest size of a tile.
+    pub tile_size: u32,
+    /// Smallest gap between tiles.
+    pub gap_size: u32,
+    /// Left to right, top to bottom.
+    pub tiles: Vec<Tile>,
+}
+
+/// Smallest share of the tile size a free-form tile is shrunk to, in percent.
+const MIN_FREE_FORM_SCALE: u32 = 70;
+
+impl LayoutStyle {
+    /// Lays out a collage around `grid_length` tiles per row and column.
+    pub fn layout(&self, grid_length: u32, tile_size: u32, gap_size: u32) -> Layout {
+        match self {
+            Self::Grid => Layout::grid(grid_length, grid_length, tile_size, gap_size),
+            Self::FreeForm => {
+                let mut rng = thread_rng();
+                let cols = rng.gen_range(grid_length.saturating_sub(1).max(2)..=grid_length + 1);
+                Layout::free_form(&mut rng, grid_length, cols, tile_size, gap_size)
+            }
+        }
+    }
+}
+
+impl Layout {
+    /// Equal tiles with equal gaps, including around the edges.
+    pub fn grid(rows: u32, cols: u32, tile_size: u32, gap_size: u32) -> Self {
+        let cell = tile_size + gap_size;
+        let tiles = (0..rows * cols)
+            .map(|i| Tile {
+                x: gap_size + cell * (i % cols),
+                y: gap_size + cell * (i / cols),
+                width: tile_size,
+                height: tile_size,
+            })
+            .collect();
+        Self {
+            width: cols * cell + gap_size,
+            height: rows * cell + gap_size,
+            rows,
+            cols,
+            tile_size,
+            gap_size,
+            tiles,
+        }
+    }
+
+    /// Tiles shrunk by a random amount and moved by a random offset within their grid cell.
+    /// Every tile stays inside its own cell, so they never overlap.
+    pub fn free_form(
+        rng: &mut impl Rng,
+        rows: u32,
+        cols: u32,
+        tile_size: u32,
+        gap_size: u32,
+    ) -> Self {
+        let mut layout = Self::grid(rows, cols, tile_size, gap_size);
+        for tile in &mut layout.tiles {
+            let size = rng.gen_range(tile_size * MIN_FREE_FORM_SCALE / 100..=tile_size);
+            tile.x += rng.gen_range(0..=tile_size - size);
+            tile.y += rng.gen_range(0..=tile_size - size);
+            tile.width = size;
+            tile.height = size;
+        }
+        layout
+    }
+
+    /// Whether this is a square grid of equal tiles, as older clients expect.
+    pub fn is_uniform(&self) -> bool {
+        self.rows == self.cols
+            && self
+                .tiles
+                .iter()
+                .all(|tile| tile.width == self.tile_size && tile.height == self.tile_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: &Tile, b: &Tile) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    fn assert_in_bounds(layout: &Layout) {
+        for tile in &layout.tiles {
+            assert!(
+                tile.x + tile.width <= layout.width,
+                "{tile:?} in {layout:?}"
+            );
+            assert!(
+                tile.y + tile.height <= layout.height,
+                "{tile:?} in {layout:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn grids_are_uniform() {
+        let layout = Layout::grid(3, 3, 10, 2);
+        assert_eq!((layout.width, layout.height), (38, 38));
+        assert_eq!(layout.tiles.len(), 9);
+        assert_eq!(
+            layout.tiles[4],
+            Tile {
+                x: 14,
+                y: 14,
+                width: 10,
+                height: 10
+            }
+        );
+        assert_in_bounds(&layout);
+        assert!(layout.is_uniform());
+        assert!(!Layout::grid(3, 4, 10, 2).is_uniform());
+    }
+
+    #[test]
+    fn free_form_tiles_stay_apart_and_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for cols in 2..=4 {
+            let layout = Layout::free_form(&mut rng, 3, cols, 20, 2);
+            assert_eq!(layout.tiles.len(), 3 * cols as usize);
+            assert_in_bounds(&layout);
+            for (i, a) in layout.tiles.iter().enumerate() {
+                assert!(a.width >= 20 * MIN_FREE_FORM_SCALE / 100 && a.width <= 20);
+                for b in &layout.tiles[i + 1..] {
+                    assert!(!overlap(a, b), "{a:?} overlaps {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn styles_give_one_tile_per_cell() {
+        for _ in 0..20 {
+            for style in [LayoutStyle::Grid, LayoutStyle::FreeForm] {
+                let layout = style.layout(3, 20, 2);
+                assert_eq!(layout.rows, 3);
+                assert_eq!(layout.tiles.len(), (layout.rows * layout.cols) as usize);
+                assert_in_bounds(&layout);
+            }
+        }
+    }
+}
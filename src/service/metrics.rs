@@ -0,0 +1,98 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Counters exported in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub challenges_issued: AtomicU64,
+    pub answers_correct: AtomicU64,
+    pub answers_wrong: AtomicU64,
+    pub tokens_valid: AtomicU64,
+    pub tokens_invalid: AtomicU64,
+    pub rate_limited: AtomicU64,
+}
+
+impl Metrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Builds a Prometheus text exposition, one metric family at a time.
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    output: String,
+}
+
+impl MetricsWriter {
+    /// Starts a metric family.
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        let _ = writeln!(self.output, "# HELP {name} {help}");
+        let _ = writeln!(self.output, "# TYPE {name} {kind}");
+        self
+    }
+
+    pub fn sample(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: impl Into<f64>,
+    ) -> &mut Self {
+        self.output.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+                .collect();
+            let _ = write!(self.output, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.output, " {}", value.into());
+        self
+    }
+
+    pub fn counter(&mut self, name: &str, help: &str, counter: &AtomicU64) -> &mut Self {
+        let value = counter.load(Ordering::Relaxed) as f64;
+        self.family(name, "counter", help).sample(name, &[], value)
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+}
+
+/// Escapes a label value as the text format asks: backslashes, double quotes and line feeds.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut writer = MetricsWriter::default();
+        writer.sample("pool_size", &[("site", "a\\b \"c\"\nd")], 1);
+        assert_eq!(
+            writer.finish(),
+            concat!(r#"pool_size{site="a\\b \"c\"\nd"} 1"#, "\n")
+        );
+    }
+
+    #[test]
+    fn families_are_described() {
+        let mut writer = MetricsWriter::default();
+        let counter = AtomicU64::new(3);
+        Metrics::increment(&counter);
+        writer.counter("answers_total", "Answers received", &counter);
+        assert_eq!(
+            writer.finish(),
+            "# HELP answers_total Answers received\n# TYPE answers_total counter\nanswers_total 4\n"
+        );
+    }
+}
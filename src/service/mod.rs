@@ -1,11 +1,44 @@
+pub mod attack;
+pub mod audio;
 pub mod challenge;
+pub mod click;
 pub mod collection;
 pub mod config;
 pub mod error;
+pub mod generator;
+pub mod grid;
+pub mod labels;
+pub mod layout;
 mod locked_file;
+pub mod metrics;
+pub mod pow;
+pub mod profile;
+pub mod risk;
+pub mod rotate;
+#[allow(clippy::module_inception)]
 pub mod service;
+pub mod site;
+pub mod solve_time;
+pub mod text;
+mod thumbnail;
+pub mod token;
 
+pub use attack::*;
+pub use audio::*;
 pub use challenge::*;
+pub use click::*;
 pub use config::*;
 pub use error::*;
+pub use generator::*;
+pub use grid::*;
+pub use labels::*;
+pub use layout::*;
+pub use pow::*;
+pub use profile::*;
+pub use risk::*;
+pub use rotate::*;
 pub use service::*;
+pub use site::*;
+pub use solve_time::*;
+pub use text::*;
+pub use token::*;
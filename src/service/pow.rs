@@ -0,0 +1,193 @@
+use rand::prelude::*;
+use sha2::{Digest, Sha256};
+
+use super::{
+    error::Error,
+    generator::{ChallengeGenerator, GeneratorContext},
+    Answer, Challenge, ChallengeKind, ChallengePayload, Difficulty, Site,
+};
+
+/// Bytes of randomness in a nonce.
+const NONCE_LENGTH: usize = 16;
+/// Hardest puzzle the service hands out. Each bit doubles the expected work.
+const MAX_BITS: u32 = 32;
+
+/// A hash preimage puzzle. The answer is any string which, appended to the nonce,
+/// gives a SHA-256 hash starting with `bits` zero bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofOfWork {
+    pub nonce: String,
+    pub bits: u32,
+}
+
+impl ProofOfWork {
+    pub fn new(bits: u32) -> Self {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        thread_rng().fill(&mut nonce);
+        Self {
+            nonce: nonce.iter().map(|byte| format!("{byte:02x}")).collect(),
+            bits: bits.min(MAX_BITS),
+        }
+    }
+
+    pub fn verify(&self, answer: &str) -> bool {
+        let hash = Sha256::new()
+            .chain_update(&self.nonce)
+            .chain_update(answer)
+            .finalize();
+        leading_zero_bits(&hash) >= self.bits
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Makes proof-of-work challenges. The expected answer records the puzzle as `bits:nonce`,
+/// since any answer which solves it is correct.
+#[derive(Debug, Default)]
+pub struct ProofOfWorkGenerator;
+
+impl ChallengeGenerator for ProofOfWorkGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::ProofOfWork
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let proof_of_work = ProofOfWork::new(context.pow_bits);
+        let expected = format!("{}:{}", proof_of_work.bits, proof_of_work.nonce);
+        Ok(Challenge::new(
+            ChallengePayload::ProofOfWork(proof_of_work),
+            expected,
+            context.profile.difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        let Answer::Text(answer) = answer else {
+            return false;
+        };
+        let Some((bits, nonce)) = expected.split_once(':') else {
+            return false;
+        };
+        let Ok(bits) = bits.parse() else {
+            return false;
+        };
+        let proof_of_work = ProofOfWork {
+            nonce: nonce.to_string(),
+            bits,
+        };
+        proof_of_work.verify(answer)
+    }
+}
+
+/// Which clients get proof-of-work challenges on the spot, and how hard they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProofOfWorkPolicy {
+    /// Leading zero bits asked of normal difficulty puzzles.
+    pub bits: u32,
+    /// Clients with a risk score up to this get proof-of-work rather than a pooled challenge.
+    pub max_risk: Option<f32>,
+}
+
+impl ProofOfWorkPolicy {
+    /// Applies a site's overrides.
+    pub fn for_site(mut self, site: &Site) -> Self {
+        if let Some(bits) = site.pow_bits {
+            self.bits = bits;
+        }
+        if let Some(max_risk) = site.pow_max_risk {
+            self.max_risk = Some(max_risk);
+        }
+        self
+    }
+
+    /// Whether a client with the given risk score gets proof-of-work.
+    pub fn applies_to(&self, score: impl FnOnce() -> f32) -> bool {
+        self.max_risk.is_some_and(|max_risk| score() <= max_risk)
+    }
+
+    /// Leading zero bits asked of a puzzle at `difficulty`.
+    pub fn bits_for(&self, difficulty: Difficulty) -> u32 {
+        match difficulty {
+            Difficulty::Easy => self.bits.saturating_sub(2),
+            Difficulty::Normal => self.bits,
+            Difficulty::Hard => self.bits + 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Searches for answers to `proof_of_work`, returning one which solves it and one which
+    /// doesn't.
+    fn answers(proof_of_work: &ProofOfWork) -> (String, String) {
+        let mut solved = None;
+        let mut unsolved = None;
+        for answer in (0..).map(|n: u32| n.to_string()) {
+            if proof_of_work.verify(&answer) {
+                solved.get_or_insert(answer);
+            } else {
+                unsolved.get_or_insert(answer);
+            }
+            if let (Some(solved), Some(unsolved)) = (&solved, &unsolved) {
+                return (solved.clone(), unsolved.clone());
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn leading_zero_bits_span_bytes() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x01, 0x00]), 7);
+        assert_eq!(leading_zero_bits(&[0x00, 0x20]), 10);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn only_solutions_are_verified() {
+        let proof_of_work = ProofOfWork::new(8);
+        let (solved, unsolved) = answers(&proof_of_work);
+        assert!(proof_of_work.verify(&solved));
+        assert!(!proof_of_work.verify(&unsolved));
+
+        let hash = Sha256::new()
+            .chain_update(&proof_of_work.nonce)
+            .chain_update(&solved)
+            .finalize();
+        assert!(hash[0] == 0);
+    }
+
+    #[test]
+    fn check_reads_the_puzzle_from_the_expected_answer() {
+        let proof_of_work = ProofOfWork::new(8);
+        let (solved, unsolved) = answers(&proof_of_work);
+        let expected = format!("8:{}", proof_of_work.nonce);
+
+        let generator = ProofOfWorkGenerator;
+        assert!(generator.check(&expected, &Answer::Text(solved.clone())));
+        assert!(!generator.check(&expected, &Answer::Text(unsolved)));
+        assert!(!generator.check(&expected, &Answer::Point { x: 0.0, y: 0.0 }));
+        assert!(!generator.check(&proof_of_work.nonce, &Answer::Text(solved.clone())));
+        assert!(!generator.check(
+            &format!("eight:{}", proof_of_work.nonce),
+            &Answer::Text(solved)
+        ));
+    }
+
+    #[test]
+    fn puzzles_are_capped() {
+        assert_eq!(ProofOfWork::new(MAX_BITS + 1).bits, MAX_BITS);
+    }
+}
@@ -0,0 +1,103 @@
+use std::ops::RangeInclusive;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Self; 3] = [Self::Easy, Self::Normal, Self::Hard];
+
+    /// The next difficulty up, if there is one.
+    pub fn harder(&self) -> Self {
+        match self {
+            Self::Easy => Self::Normal,
+            Self::Normal | Self::Hard => Self::Hard,
+        }
+    }
+
+    /// How many collections are mixed into one grid.
+    pub fn collections(&self) -> RangeInclusive<usize> {
+        match self {
+            Self::Easy => 2..=2,
+            Self::Normal => 2..=5,
+            Self::Hard => 4..=6,
+        }
+    }
+
+    /// How many tiles of an odd one out grid don't belong.
+    pub fn intruders(&self) -> RangeInclusive<usize> {
+        match self {
+            Self::Easy => 1..=1,
+            Self::Normal => 1..=2,
+            Self::Hard => 2..=3,
+        }
+    }
+
+    /// How many degrees off upright an answer to a rotate challenge may be.
+    pub fn rotation_tolerance(&self) -> u32 {
+        match self {
+            Self::Easy => 20,
+            Self::Normal => 15,
+            Self::Hard => 10,
+        }
+    }
+
+    /// How many pixels outside the target a click may land.
+    pub fn click_tolerance(&self) -> u32 {
+        match self {
+            Self::Easy => 8,
+            Self::Normal => 4,
+            Self::Hard => 0,
+        }
+    }
+
+    /// How many words an audio challenge reads out.
+    pub fn audio_length(&self) -> usize {
+        match self {
+            Self::Easy => 4,
+            Self::Normal => 5,
+            Self::Hard => 6,
+        }
+    }
+
+    /// Loudest noise mixed into an audio challenge, as a share of full scale.
+    pub fn audio_noise(&self) -> f32 {
+        match self {
+            Self::Easy => 0.02,
+            Self::Normal => 0.05,
+            Self::Hard => 0.1,
+        }
+    }
+
+    pub fn grid_length(&self, base: u32) -> u32 {
+        match self {
+            Self::Hard => base + 1,
+            _ => base,
+        }
+    }
+}
+
+/// Identifies a pool of pre-generated challenges.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Profile {
+    pub site_key: Option<String>,
+    pub difficulty: Difficulty,
+}
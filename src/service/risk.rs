@@ -0,0 +1,356 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{ClientInfo, Difficulty};
+
+/// Solve times at or above this look human. Faster ones grow more suspicious.
+const HUMAN_SOLVE_TIME: Duration = Duration::from_secs(3);
+/// Answers needed before the failure ratio counts in full.
+const MIN_ANSWERS: usize = 3;
+/// Binding mismatches within the window which make a client fully suspicious.
+const MAX_MISMATCHES: usize = 2;
+/// User agent fragments of common automation tools.
+const AUTOMATION_AGENTS: [&str; 7] = [
+    "curl",
+    "wget",
+    "python",
+    "go-http-client",
+    "headless",
+    "phantomjs",
+    "selenium",
+];
+
+const RATE_WEIGHT: f32 = 0.3;
+const FAILURE_WEIGHT: f32 = 0.3;
+const SOLVE_TIME_WEIGHT: f32 = 0.2;
+const HEADER_WEIGHT: f32 = 0.2;
+const MISMATCH_WEIGHT: f32 = 0.3;
+
+/// Recent activity of one IP address.
+#[derive(Debug, Default)]
+struct ClientHistory {
+    requests: VecDeque<Instant>,
+    answers: VecDeque<(Instant, bool)>,
+    mismatches: VecDeque<Instant>,
+}
+
+impl ClientHistory {
+    fn forget_before(&mut self, cutoff: Instant) {
+        self.requests.retain(|at| *at >= cutoff);
+        self.answers.retain(|(at, _)| *at >= cutoff);
+        self.mismatches.retain(|at| *at >= cutoff);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.requests.is_empty() && self.answers.is_empty() && self.mismatches.is_empty()
+    }
+
+    fn failure_ratio(&self) -> f32 {
+        let failures = self.answers.iter().filter(|(_, correct)| !correct).count();
+        failures as f32 / self.answers.len().max(MIN_ANSWERS) as f32
+    }
+}
+
+/// What the risk scorer makes of a client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiskAssessment {
+    /// Between 0 (benign) and 1 (hostile).
+    pub score: f32,
+    /// Difficulty the client's next challenge should have.
+    pub difficulty: Difficulty,
+    /// Correct answers the client should give before it gets a token.
+    pub rounds: u32,
+}
+
+/// Scores clients from their recent behaviour and their request headers.
+#[derive(Debug)]
+pub struct RiskScorer {
+    window: Duration,
+    max_requests: usize,
+    clients: Mutex<HashMap<IpAddr, ClientHistory>>,
+    last_prune: Mutex<Instant>,
+}
+
+impl RiskScorer {
+    /// Remembers activity for `window`, and considers `max_requests` challenge requests
+    /// within it to be fully suspicious.
+    pub fn new(window: Duration, max_requests: usize) -> Self {
+        Self {
+            window,
+            max_requests: max_requests.max(1),
+            clients: Mutex::new(HashMap::new()),
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn record(&self, ip: Option<IpAddr>, update: impl FnOnce(&mut ClientHistory, Instant)) {
+        let Some(ip) = ip else {
+            return;
+        };
+        let now = Instant::now();
+        let cutoff = now.checked_sub(self.window).unwrap_or(now);
+        let mut clients = self.clients.lock().unwrap();
+
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if now.duration_since(*last_prune) >= self.window {
+            clients.retain(|_, history| {
+                history.forget_before(cutoff);
+                !history.is_empty()
+            });
+            *last_prune = now;
+        }
+
+        update(clients.entry(ip).or_default(), now);
+    }
+
+    pub fn record_request(&self, client: &ClientInfo) {
+        self.record(client.ip, |history, now| history.requests.push_back(now));
+    }
+
+    pub fn record_answer(&self, client: &ClientInfo, correct: bool) {
+        self.record(client.ip, |history, now| {
+            history.answers.push_back((now, correct))
+        });
+    }
+
+    /// Records that a token solved from `ip` was presented for a different client.
+    pub fn record_mismatch(&self, ip: Option<IpAddr>) {
+        self.record(ip, |history, now| history.mismatches.push_back(now));
+    }
+
+    /// Challenge requests from `ip` within the last `within`, up to the length of the window.
+    pub fn requests_within(&self, ip: IpAddr, within: Duration) -> usize {
+        let now = Instant::now();
+        let cutoff = now.checked_sub(within).unwrap_or(now);
+        self.clients.lock().unwrap().get(&ip).map_or(0, |history| {
+            history.requests.iter().filter(|at| **at >= cutoff).count()
+        })
+    }
+
+    /// Combines the client's recent history, its headers and, once known, how long it took
+    /// to solve a challenge.
+    pub fn score(&self, client: &ClientInfo, solve_time: Option<Duration>) -> f32 {
+        let mut score = HEADER_WEIGHT * header_anomalies(client);
+
+        if let Some(solve_time) = solve_time {
+            let speed = 1.0 - solve_time.as_secs_f32() / HUMAN_SOLVE_TIME.as_secs_f32();
+            score += SOLVE_TIME_WEIGHT * speed.max(0.0);
+        }
+
+        if let Some(ip) = client.ip {
+            let now = Instant::now();
+            let cutoff = now.checked_sub(self.window).unwrap_or(now);
+            let mut clients = self.clients.lock().unwrap();
+            if let Some(history) = clients.get_mut(&ip) {
+                history.forget_before(cutoff);
+                let rate = history.requests.len() as f32 / self.max_requests as f32;
+                let mismatches = history.mismatches.len() as f32 / MAX_MISMATCHES as f32;
+                score += RATE_WEIGHT * rate.min(1.0)
+                    + FAILURE_WEIGHT * history.failure_ratio()
+                    + MISMATCH_WEIGHT * mismatches.min(1.0);
+            }
+        }
+
+        score.min(1.0)
+    }
+
+    /// Picks the difficulty and number of rounds for a client's next challenge,
+    /// never going below `base`.
+    pub fn assess(&self, client: &ClientInfo, base: Difficulty) -> RiskAssessment {
+        let score = self.score(client, None);
+        let difficulty = match score {
+            s if s < 0.3 => base,
+            s if s < 0.6 => base.harder(),
+            _ => Difficulty::Hard,
+        };
+        let rounds = match score {
+            s if s < 0.5 => 1,
+            s if s < 0.8 => 2,
+            _ => 3,
+        };
+
+        RiskAssessment {
+            score,
+            difficulty,
+            rounds,
+        }
+    }
+}
+
+/// Share of the usual browser headers which are missing or look automated.
+fn header_anomalies(client: &ClientInfo) -> f32 {
+    let automated = client.user_agent.as_ref().is_none_or(|user_agent| {
+        let user_agent = user_agent.to_lowercase();
+        AUTOMATION_AGENTS
+            .iter()
+            .any(|agent| user_agent.contains(agent))
+    });
+    let anomalies = [
+        automated,
+        client.accept_language.is_none(),
+        client.origin.is_none(),
+    ];
+
+    anomalies.iter().filter(|anomaly| **anomaly).count() as f32 / anomalies.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn browser(ip: [u8; 4]) -> ClientInfo {
+        ClientInfo {
+            origin: Some("https://example.com".to_string()),
+            ip: Some(IpAddr::from(ip)),
+            user_agent: Some("Mozilla/5.0 Firefox/128.0".to_string()),
+            accept_language: Some("en".to_string()),
+        }
+    }
+
+    fn scorer() -> RiskScorer {
+        RiskScorer::new(Duration::from_secs(60), 10)
+    }
+
+    #[test]
+    fn new_browsers_are_benign() {
+        let scorer = scorer();
+        let client = browser([192, 0, 2, 1]);
+
+        assert_eq!(scorer.score(&client, None), 0.0);
+        assert_eq!(
+            scorer.assess(&client, Difficulty::Easy),
+            RiskAssessment {
+                score: 0.0,
+                difficulty: Difficulty::Easy,
+                rounds: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn automated_headers_are_anomalies() {
+        let client = browser([192, 0, 2, 1]);
+        let curl = ClientInfo {
+            user_agent: Some("curl/8.5.0".to_string()),
+            ..client.clone()
+        };
+        let bare = ClientInfo {
+            ip: client.ip,
+            ..Default::default()
+        };
+
+        assert_eq!(header_anomalies(&client), 0.0);
+        assert_eq!(header_anomalies(&curl), 1.0 / 3.0);
+        assert_eq!(header_anomalies(&bare), 1.0);
+    }
+
+    #[test]
+    fn fast_solves_are_suspicious() {
+        let scorer = scorer();
+        let client = browser([192, 0, 2, 1]);
+
+        assert_eq!(
+            scorer.score(&client, Some(Duration::ZERO)),
+            SOLVE_TIME_WEIGHT
+        );
+        assert_eq!(scorer.score(&client, Some(HUMAN_SOLVE_TIME)), 0.0);
+        assert_eq!(scorer.score(&client, Some(HUMAN_SOLVE_TIME * 2)), 0.0);
+    }
+
+    #[test]
+    fn history_raises_the_score_of_its_client_only() {
+        let scorer = scorer();
+        let client = browser([192, 0, 2, 1]);
+        for _ in 0..10 {
+            scorer.record_request(&client);
+        }
+        assert_eq!(scorer.score(&client, None), RATE_WEIGHT);
+
+        for _ in 0..MIN_ANSWERS {
+            scorer.record_answer(&client, false);
+        }
+        assert_eq!(scorer.score(&client, None), RATE_WEIGHT + FAILURE_WEIGHT);
+
+        assert_eq!(scorer.score(&browser([192, 0, 2, 2]), None), 0.0);
+    }
+
+    #[test]
+    fn a_few_failures_count_for_less() {
+        let scorer = scorer();
+        let client = browser([192, 0, 2, 1]);
+        scorer.record_answer(&client, false);
+
+        let ratio = 1.0 / MIN_ANSWERS as f32;
+        assert_eq!(scorer.score(&client, None), FAILURE_WEIGHT * ratio);
+    }
+
+    #[test]
+    fn hostile_clients_get_the_hardest_challenges() {
+        let scorer = scorer();
+        let client = ClientInfo {
+            ip: Some(IpAddr::from([192, 0, 2, 1])),
+            ..Default::default()
+        };
+        for _ in 0..10 {
+            scorer.record_request(&client);
+            scorer.record_answer(&client, false);
+        }
+        for _ in 0..MAX_MISMATCHES {
+            scorer.record_mismatch(client.ip);
+        }
+
+        let assessment = scorer.assess(&client, Difficulty::Easy);
+        assert_eq!(assessment.score, 1.0);
+        assert_eq!(assessment.difficulty, Difficulty::Hard);
+        assert_eq!(assessment.rounds, 3);
+    }
+
+    #[test]
+    fn moderate_risk_raises_the_difficulty_a_step() {
+        let scorer = scorer();
+        // Headers and half the request budget give 0.2 + 0.15
+        let client = ClientInfo {
+            ip: Some(IpAddr::from([192, 0, 2, 1])),
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            scorer.record_request(&client);
+        }
+
+        let assessment = scorer.assess(&client, Difficulty::Easy);
+        assert_eq!(assessment.difficulty, Difficulty::Normal);
+        assert_eq!(assessment.rounds, 1);
+    }
+
+    #[test]
+    fn requests_are_counted_within_the_rate_limit_window() {
+        let scorer = scorer();
+        let client = browser([192, 0, 2, 1]);
+        let ip = client.ip.unwrap();
+        scorer.record_request(&client);
+        std::thread::sleep(Duration::from_millis(20));
+        scorer.record_request(&client);
+
+        assert_eq!(scorer.requests_within(ip, Duration::from_secs(60)), 2);
+        assert_eq!(scorer.requests_within(ip, Duration::from_millis(10)), 1);
+        assert_eq!(
+            scorer.requests_within(IpAddr::from([192, 0, 2, 2]), HUMAN_SOLVE_TIME),
+            0
+        );
+    }
+
+    #[test]
+    fn history_is_forgotten_after_the_window() {
+        let scorer = RiskScorer::new(Duration::ZERO, 1);
+        let client = browser([192, 0, 2, 1]);
+        scorer.record_request(&client);
+        scorer.record_answer(&client, false);
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(scorer.score(&client, None), 0.0);
+    }
+}
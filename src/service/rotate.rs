@@ -0,0 +1,169 @@
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use rand::prelude::*;
+use snafu::prelude::*;
+use std::io::Cursor;
+
+use super::{
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    Answer, Challenge, ChallengeKind, ChallengePayload,
+};
+
+/// A single image turned away from upright, which must be rotated back. The answer is the
+/// clockwise rotation in degrees which makes it upright, and the expected answer records the
+/// puzzle as `angle:tolerance`.
+#[derive(Debug, Default)]
+pub struct RotateGenerator;
+
+/// Samples `img` at a fractional position, blending the four nearest pixels.
+fn sample_bilinear(img: &DynamicImage, x: f32, y: f32) -> Rgba<u8> {
+    let max_x = img.width() as f32 - 1.0;
+    let max_y = img.height() as f32 - 1.0;
+    let (x, y) = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+    let (x0, y0) = (x.floor(), y.floor());
+    let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+    let (fx, fy) = (x - x0, y - y0);
+
+    let corners = [
+        (img.get_pixel(x0 as u32, y0 as u32), (1.0 - fx) * (1.0 - fy)),
+        (img.get_pixel(x1 as u32, y0 as u32), fx * (1.0 - fy)),
+        (img.get_pixel(x0 as u32, y1 as u32), (1.0 - fx) * fy),
+        (img.get_pixel(x1 as u32, y1 as u32), fx * fy),
+    ];
+    let mut pixel = [0u8; 4];
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        let blended: f32 = corners
+            .iter()
+            .map(|(corner, weight)| corner.0[channel] as f32 * weight)
+            .sum();
+        *value = blended.round() as u8;
+    }
+    Rgba(pixel)
+}
+
+/// Draws the circle in the middle of `img` turned `angle` degrees clockwise, on a transparent
+/// square of `size` pixels. Cropping to a circle keeps the outline from giving away the angle.
+fn rotate_image(img: &DynamicImage, angle: f32, size: u32) -> RgbaImage {
+    let radius = img.width().min(img.height()) as f32 / 2.0;
+    let (source_x, source_y) = (img.width() as f32 / 2.0, img.height() as f32 / 2.0);
+    let center = size as f32 / 2.0;
+    let (sin, cos) = angle.to_radians().sin_cos();
+
+    RgbaImage::from_fn(size, size, |x, y| {
+        let dx = x as f32 + 0.5 - center;
+        let dy = y as f32 + 0.5 - center;
+        if dx * dx + dy * dy > radius * radius {
+            return Rgba([0u8, 0u8, 0u8, 0u8]);
+        }
+        // Turn back the other way to find where the pixel comes from
+        sample_bilinear(
+            img,
+            source_x + dx * cos + dy * sin - 0.5,
+            source_y - dx * sin + dy * cos - 0.5,
+        )
+    })
+}
+
+impl ChallengeGenerator for RotateGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Rotate
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        let images: Vec<_> = context
+            .collections
+            .iter()
+            .filter(|collection| collection.metadata.rotatable)
+            .flat_map(|collection| collection.images.iter())
+            .collect();
+        let img = images
+            .choose(&mut rng)
+            .context(InsufficientCollectionsSnafu {})?;
+
+        // Start far enough from upright that the image needs turning
+        let difficulty = context.profile.difficulty;
+        let tolerance = difficulty.rotation_tolerance();
+        let angle = rng.gen_range(2 * tolerance..=360 - 2 * tolerance);
+
+        tracing::trace!("Rotating {} by {angle} degrees", img.display());
+        let rotated = rotate_image(&context.thumbnail(img)?, angle as f32, context.image_size);
+
+        let mut image = Vec::new();
+        tracing::debug!("Generating image");
+        rotated
+            .write_to(&mut Cursor::new(&mut image), ImageFormat::WebP)
+            .context(GenerateImageSnafu {})?;
+
+        let payload = ChallengePayload::Rotate {
+            image,
+            image_size: context.image_size,
+        };
+        Ok(Challenge::new(
+            payload,
+            format!("{angle}:{tolerance}"),
+            difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+
+    fn check(&self, expected: &str, answer: &Answer) -> bool {
+        let Answer::Text(answer) = answer else {
+            return false;
+        };
+        let Some((angle, tolerance)) = expected.split_once(':') else {
+            return false;
+        };
+        let (Ok(angle), Ok(tolerance), Ok(answer)) = (
+            angle.parse::<f64>(),
+            tolerance.parse::<f64>(),
+            answer.trim().parse::<f64>(),
+        ) else {
+            return false;
+        };
+        if !answer.is_finite() {
+            return false;
+        }
+        // How far from upright the image ends up, either way round
+        let off = (angle + answer).rem_euclid(360.0);
+        off.min(360.0 - off) <= tolerance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(expected: &str, answer: &str) -> bool {
+        RotateGenerator.check(expected, &Answer::Text(answer.to_string()))
+    }
+
+    #[test]
+    fn answers_within_tolerance_pass() {
+        assert!(check("90:15", "270"));
+        assert!(check("90:15", "285"));
+        assert!(check("90:15", "255"));
+        assert!(!check("90:15", "286"));
+        assert!(!check("90:15", "254"));
+        assert!(!check("90:15", "90"));
+    }
+
+    #[test]
+    fn answers_wrap_around() {
+        assert!(check("350:15", "10"));
+        assert!(check("350:15", "-350"));
+        assert!(check("10:15", "355"));
+        assert!(check("10:15", "710"));
+        assert!(!check("350:15", "30"));
+    }
+
+    #[test]
+    fn malformed_answers_fail() {
+        assert!(!check("90:15", "right"));
+        assert!(!check("90:15", "inf"));
+        assert!(!check("90:15", "NaN"));
+        assert!(!check("90", "270"));
+        assert!(!RotateGenerator.check("90:15", &Answer::Point { x: 270.0, y: 0.0 }));
+    }
+}
@@ -1,265 +1,1224 @@
-use image::{imageops::FilterType, DynamicImage, GenericImage, ImageFormat, Rgba, RgbaImage};
 use rand::prelude::*;
 use snafu::prelude::*;
 use std::{
-    collections::{HashMap, HashSet},
-    ffi::OsString,
-    io::{BufReader, Cursor, Seek},
+    collections::HashMap,
+    net::IpAddr,
     path::{Path, PathBuf},
-    sync::{Mutex, RwLock},
-    time::{Duration, Instant},
+    sync::{atomic::Ordering, Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
-use uuid::Uuid;
 
-use super::{challenge::Challenge, collection::Collection, error::*, locked_file::LockedFile};
+use super::{
+    attack::{AttackMode, AttackStatus},
+    audio::{read_clip, AudioGenerator},
+    challenge::{Answer, AnswerOutcome, Challenge, ChallengeKind, ChallengeRequest, ClientInfo},
+    click::ClickGenerator,
+    collection::{
+        AudioClip, AudioCollection, Collection, CollectionMetadata, ImageMetadata,
+        BACKGROUNDS_COLLECTION, IMAGE_METADATA_EXTENSION, METADATA_FILE, UNLABELLED_COLLECTION,
+    },
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    grid::{split_tiles, GridGenerator, OddOneOutGenerator},
+    labels::{ungraded_selections, LabelPolicy, LabelProposal, Labels},
+    layout::{Layout, LayoutStyle},
+    metrics::{Metrics, MetricsWriter},
+    pow::{ProofOfWorkGenerator, ProofOfWorkPolicy},
+    profile::{Difficulty, Profile},
+    risk::RiskScorer,
+    rotate::RotateGenerator,
+    site::{secrets_match, Site},
+    solve_time::{SolveTimeLimits, SolveTimePolicy, SolveTimeViolation},
+    text::TextGenerator,
+    thumbnail::{get_thumbnail_path, load_thumbnail, THUMBNAIL_PREFIX},
+    token::{Mismatch, TokenRequest, ValidatedToken},
+};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-const THUMBNAIL_PREFIX: &str = &".thumbnail.";
-const THUMBNAIL_FORMAT: ImageFormat = ImageFormat::WebP;
+/// How long a client waits for the generators before giving up.
+const CHALLENGE_WAIT: Duration = Duration::from_secs(10);
+/// How long a generator sleeps when every pool is full and there are no thumbnails to make.
+const GENERATOR_IDLE: Duration = Duration::from_millis(100);
+/// How long a pool is skipped after its generation fails, doubled for each failure in a row.
+const GENERATOR_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest a pool is skipped for after failing.
+const MAX_GENERATOR_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_ACTION_LENGTH: usize = 32;
+const MAX_CDATA_LENGTH: usize = 255;
+/// Rate limits count the challenge requests over this window.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+type Pool = Arc<deadqueue::resizable::Queue<Challenge>>;
+
+/// Where the challenge for a request comes from.
+enum Source {
+    /// Image challenges are generated ahead of time.
+    Pool(Pool),
+    /// Proof-of-work challenges are cheap enough to make on the spot, and alternatives are
+    /// asked for too rarely to keep pools of.
+    OnTheSpot(Profile, ChallengeKind),
+}
+
+#[derive(Debug)]
+struct PendingChallenge {
+    answer: String,
+    topic: Option<String>,
+    site_key: Option<String>,
+    issued_at: Option<SystemTime>,
+    expires_at: Option<SystemTime>,
+    action: Option<String>,
+    cdata: Option<String>,
+    session: Option<String>,
+    image: Option<Vec<u8>>,
+    example: Option<Vec<u8>>,
+    /// Tiles of the collage held for fetching one by one, by their random keys.
+    tiles: HashMap<String, Vec<u8>>,
+    kind: ChallengeKind,
+    /// Images of the unlabelled pool shown ungraded, whose selection is recorded.
+    unlabelled: Vec<PathBuf>,
+}
+
+impl PendingChallenge {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Failures in a row of a pool's generation, and when to try it again.
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+/// Progress through a multi-round challenge.
+#[derive(Debug)]
+struct Session {
+    site_key: Option<String>,
+    action: Option<String>,
+    cdata: Option<String>,
+    rounds: u32,
+    completed: u32,
+    /// Challenge handed out for the current round, if any.
+    current: Option<String>,
+    expires_at: SystemTime,
+}
 
 #[derive(Debug)]
 pub struct ImHumane {
-    queue: deadqueue::resizable::Queue<Challenge>,
+    pools: RwLock<HashMap<Profile, Pool>>,
+    /// Pools skipped by the generators since their generation failed.
+    backoff: Mutex<HashMap<Profile, Backoff>>,
     thumbnail_queue: deadqueue::unlimited::Queue<PathBuf>,
     collections: RwLock<Vec<Collection>>,
-    answers: Mutex<HashMap<String, String>>,
-    validated_tokens: Mutex<HashSet<String>>,
+    /// Images of the backgrounds collection, kept apart from the others.
+    backgrounds: RwLock<Vec<PathBuf>>,
+    audio: RwLock<Vec<AudioCollection>>,
+    /// Images of the unlabelled collection, shown ungraded until answers agree on a topic.
+    unlabelled: RwLock<Vec<PathBuf>>,
+    labels: Labels,
+    sites: RwLock<HashMap<String, Site>>,
+    answers: Mutex<HashMap<String, PendingChallenge>>,
+    sessions: Mutex<HashMap<String, Session>>,
+    validated_tokens: Mutex<HashMap<String, ValidatedToken>>,
+    buffer_size: usize,
     image_size: u32,
     gap_size: u32,
     grid_length: u32,
+    layout: LayoutStyle,
+    example_prompts: bool,
+    challenge_lifetime: Duration,
+    token_lifetime: Duration,
+    trusted_proxies: Vec<IpAddr>,
+    max_batch_size: usize,
+    solve_time: SolveTimeLimits,
+    rounds: u32,
+    risk: RiskScorer,
+    adaptive_difficulty: bool,
+    rate_limit: Option<usize>,
+    attack: AttackMode,
+    admin_token: Option<String>,
+    metrics: Metrics,
+    proof_of_work: ProofOfWorkPolicy,
+    challenge_kinds: Vec<ChallengeKind>,
+    generators: HashMap<ChallengeKind, Box<dyn ChallengeGenerator>>,
 }
 
-fn get_thumbnail_path(img_path: &PathBuf) -> PathBuf {
-    // Fancy filename gen to avoid an unnecessary conversion to str
-    let mut thumbnail = OsString::from(THUMBNAIL_PREFIX);
-    thumbnail.push(img_path.file_stem().unwrap());
-    thumbnail.push(".webp");
-    img_path.with_file_name(thumbnail)
+/// Actions and custom data end up in headers and logs, so they are kept short and plain.
+fn is_plain(value: &str, max_length: usize) -> bool {
+    value.len() <= max_length
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
 
 impl ImHumane {
-    pub fn new(buffer_size: usize, image_size: u32, gap_size: u32, grid_length: u32) -> Self {
-        Self {
-            queue: deadqueue::resizable::Queue::new(buffer_size),
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        buffer_size: usize,
+        image_size: u32,
+        gap_size: u32,
+        grid_length: u32,
+        layout: LayoutStyle,
+        example_prompts: bool,
+        challenge_lifetime: Duration,
+        token_lifetime: Duration,
+        trusted_proxies: Vec<IpAddr>,
+        max_batch_size: usize,
+        solve_time: SolveTimeLimits,
+        rounds: u32,
+        risk: RiskScorer,
+        adaptive_difficulty: bool,
+        rate_limit: Option<usize>,
+        attack: AttackMode,
+        admin_token: Option<String>,
+        proof_of_work: ProofOfWorkPolicy,
+        challenge_kinds: Vec<ChallengeKind>,
+        labels: LabelPolicy,
+    ) -> Self {
+        let mut service = Self {
+            pools: RwLock::new(HashMap::new()),
+            backoff: Mutex::new(HashMap::new()),
             thumbnail_queue: deadqueue::unlimited::Queue::new(),
             collections: RwLock::new(Vec::new()),
+            backgrounds: RwLock::new(Vec::new()),
+            audio: RwLock::new(Vec::new()),
+            unlabelled: RwLock::new(Vec::new()),
+            labels: Labels::new(labels),
+            sites: RwLock::new(HashMap::new()),
             answers: Mutex::new(HashMap::new()),
-            validated_tokens: Mutex::new(HashSet::new()),
+            sessions: Mutex::new(HashMap::new()),
+            validated_tokens: Mutex::new(HashMap::new()),
+            buffer_size,
             image_size,
             gap_size,
             grid_length,
+            layout,
+            example_prompts,
+            challenge_lifetime,
+            token_lifetime,
+            trusted_proxies,
+            max_batch_size,
+            solve_time,
+            rounds: rounds.max(1),
+            risk,
+            adaptive_difficulty,
+            rate_limit,
+            attack,
+            admin_token,
+            metrics: Metrics::default(),
+            proof_of_work,
+            challenge_kinds,
+            generators: HashMap::new(),
+        };
+        service.add_generator(Box::new(GridGenerator));
+        service.add_generator(Box::new(OddOneOutGenerator));
+        service.add_generator(Box::new(ProofOfWorkGenerator));
+        service.add_generator(Box::new(RotateGenerator));
+        service.add_generator(Box::new(ClickGenerator));
+        service.add_generator(Box::new(AudioGenerator));
+        service.add_generator(Box::new(TextGenerator));
+        service.add_pools(
+            &mut service.pools.write().unwrap(),
+            None,
+            Difficulty::default(),
+        );
+        service
+    }
+
+    /// Whether a site (or requests without one) at `base` difficulty may be served challenges
+    /// at `difficulty`: its own, harder ones when those are picked by risk, and hard ones, which
+    /// attack mode switches every request to.
+    fn serves_difficulty(&self, base: Difficulty, difficulty: Difficulty) -> bool {
+        difficulty == base
+            || difficulty == Difficulty::Hard
+            || (self.adaptive_difficulty && difficulty > base)
+    }
+
+    /// Adds the pools serving a site (or requests without one) at `base` difficulty, and at
+    /// the other difficulties it may be served.
+    fn add_pools(
+        &self,
+        pools: &mut HashMap<Profile, Pool>,
+        site_key: Option<&str>,
+        base: Difficulty,
+    ) {
+        for difficulty in Difficulty::ALL {
+            if self.serves_difficulty(base, difficulty) {
+                pools
+                    .entry(Profile {
+                        site_key: site_key.map(str::to_string),
+                        difficulty,
+                    })
+                    .or_insert_with(|| {
+                        Arc::new(deadqueue::resizable::Queue::new(self.buffer_size))
+                    });
+            }
         }
     }
 
+    /// Plugs in a generator, replacing the one for the same kind of challenge.
+    pub fn add_generator(&mut self, generator: Box<dyn ChallengeGenerator>) {
+        self.generators.insert(generator.kind(), generator);
+    }
+
+    pub fn trusted_proxies(&self) -> &[IpAddr] {
+        &self.trusted_proxies
+    }
+
     pub fn empty(&self) -> bool {
-        self.queue.is_empty()
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .all(|pool| pool.is_empty())
     }
 
-    pub fn try_get_challenge(&self) -> Option<Challenge> {
-        self.queue.try_pop()
+    pub fn site(&self, site_key: &str) -> Option<Site> {
+        self.sites.read().unwrap().get(site_key).cloned()
     }
 
-    pub async fn get_challenge(&self) -> Challenge {
-        self.queue.pop().await
+    /// Finds the site a secret key belongs to.
+    pub fn site_for_secret(&self, secret_key: &str) -> Result<Site> {
+        self.sites
+            .read()
+            .unwrap()
+            .values()
+            .find(|site| site.has_secret(secret_key))
+            .cloned()
+            .context(InvalidSecretSnafu)
     }
 
-    pub fn check_answer(&self, challenge_id: String, answer: String) -> bool {
-        if let Some(correct_answer) = self.answers.lock().unwrap().remove(&challenge_id) {
-            tracing::debug!(
-                answer = answer,
-                challenge_id = challenge_id,
-                correct_answer = correct_answer,
-                "Checking answer",
+    /// Works out which pool a request should be served from and how many rounds it needs,
+    /// stepping both up under attack, and for risky clients when adaptive difficulty is enabled.
+    fn profile_for(&self, request: &ChallengeRequest) -> Result<(Profile, u32)> {
+        let profile = self.base_profile_for(request)?;
+        let rounds = profile
+            .site_key
+            .as_deref()
+            .and_then(|site_key| self.site(site_key))
+            .and_then(|site| site.rounds)
+            .unwrap_or(self.rounds);
+
+        if self.attack.is_active() {
+            let profile = Profile {
+                difficulty: Difficulty::Hard,
+                ..profile
+            };
+            return Ok((profile, rounds.max(self.attack.policy().rounds)));
+        }
+        if !self.adaptive_difficulty {
+            return Ok((profile, rounds));
+        }
+
+        let assessment = self.risk.assess(&request.client, profile.difficulty);
+        tracing::debug!(
+            score = assessment.score,
+            difficulty = ?assessment.difficulty,
+            rounds = assessment.rounds,
+            "Assessed client risk",
+        );
+        let profile = Profile {
+            difficulty: assessment.difficulty,
+            ..profile
+        };
+        Ok((profile, rounds.max(assessment.rounds)))
+    }
+
+    fn base_profile_for(&self, request: &ChallengeRequest) -> Result<Profile> {
+        if let Some(action) = &request.action {
+            ensure!(
+                is_plain(action, MAX_ACTION_LENGTH),
+                InvalidActionSnafu { action }
             );
-            if correct_answer == answer {
-                self.validated_tokens.lock().unwrap().insert(challenge_id);
-                return true;
+        }
+        if let Some(cdata) = &request.cdata {
+            ensure!(is_plain(cdata, MAX_CDATA_LENGTH), InvalidCdataSnafu);
+        }
+
+        let Some(site_key) = &request.site_key else {
+            return Ok(Profile::default());
+        };
+
+        let site = self.site(site_key).context(UnknownSiteSnafu { site_key })?;
+
+        if let Some(origin) = &request.client.origin {
+            ensure!(
+                site.allows_origin(origin),
+                OriginNotAllowedSnafu { origin, site_key }
+            );
+        }
+
+        Ok(Profile {
+            site_key: Some(site.site_key),
+            difficulty: site.difficulty,
+        })
+    }
+
+    fn pool(&self, profile: &Profile) -> Result<Pool> {
+        self.pools
+            .read()
+            .unwrap()
+            .get(profile)
+            .cloned()
+            .context(NotReadySnafu)
+    }
+
+    /// Whether to make a proof-of-work challenge on the spot rather than serve one from a pool.
+    /// Attack mode never does.
+    fn wants_proof_of_work(&self, request: &ChallengeRequest, profile: &Profile) -> bool {
+        if self.attack.is_active() {
+            return false;
+        }
+        let site = profile
+            .site_key
+            .as_deref()
+            .and_then(|site_key| self.site(site_key));
+        self.proof_of_work_policy(site.as_ref())
+            .applies_to(|| self.risk.score(&request.client, None))
+    }
+
+    fn proof_of_work_policy(&self, site: Option<&Site>) -> ProofOfWorkPolicy {
+        site.map_or(self.proof_of_work, |site| self.proof_of_work.for_site(site))
+    }
+
+    /// Records a challenge request, enforces the rate limit, and works out what to serve.
+    fn admit(&self, request: &ChallengeRequest) -> Result<(ChallengeRequest, Source, u32)> {
+        self.risk.record_request(&request.client);
+        self.attack.record_request();
+
+        let under_attack = self.attack.is_active();
+        let rate_limit = match (self.rate_limit, under_attack) {
+            (Some(limit), true) => Some(limit.min(self.attack.policy().rate_limit)),
+            (None, true) => Some(self.attack.policy().rate_limit),
+            (limit, false) => limit,
+        };
+        if let (Some(limit), Some(ip)) = (rate_limit, request.client.ip) {
+            if self.risk.requests_within(ip, RATE_LIMIT_WINDOW) > limit {
+                Metrics::increment(&self.metrics.rate_limited);
+                return RateLimitedSnafu { ip }.fail();
             }
         }
-        return false;
+
+        let request = self.resume_session(request)?;
+        let (profile, rounds) = self.profile_for(&request)?;
+        let source = match request.kind {
+            Some(kind) => {
+                ensure!(
+                    kind.is_alternative() && self.offers(kind, &profile),
+                    KindNotOfferedSnafu { kind }
+                );
+                Source::OnTheSpot(profile, kind)
+            }
+            None if self.wants_proof_of_work(&request, &profile) => {
+                Source::OnTheSpot(profile, ChallengeKind::ProofOfWork)
+            }
+            None => Source::Pool(self.pool(&profile)?),
+        };
+        Ok((request, source, rounds))
     }
 
-    pub fn check_token(&self, challenge_id: String) -> bool {
-        self.validated_tokens.lock().unwrap().remove(&challenge_id)
+    pub fn try_get_challenge(&self, request: &ChallengeRequest) -> Result<Option<Challenge>> {
+        let (request, source, rounds) = self.admit(request)?;
+        let challenge = match source {
+            Source::Pool(pool) => pool.try_pop(),
+            Source::OnTheSpot(profile, kind) => Some(self.challenge_on_the_spot(&profile, kind)?),
+        };
+        challenge
+            .map(|challenge| self.issue(challenge, &request, rounds))
+            .transpose()
     }
 
-    pub fn run_generator(&self, handle: tokio::runtime::Handle) {
-        // This function relies on the limited capacity of the queue
-        // to limit the number of challenges generated.
-        loop {
-            let start = Instant::now();
-            match self.generate() {
-                Ok(challenge) => {
-                    self.answers
-                        .lock()
-                        .unwrap()
-                        .insert(challenge.id.clone(), challenge.answer.clone());
-                    tracing::debug!(
-                        time_ms = start.elapsed().as_millis(),
-                        challenge_id = challenge.id,
-                        challenge_topic = challenge.topic,
-                        challenge_answer = challenge.answer,
-                        "Challenge generated.",
-                    );
+    pub async fn get_challenge(self: &Arc<Self>, request: &ChallengeRequest) -> Result<Challenge> {
+        let (request, source, rounds) = self.admit(request)?;
+        let challenge = match source {
+            Source::Pool(pool) => tokio::time::timeout(CHALLENGE_WAIT, pool.pop())
+                .await
+                .map_err(|_| NotReadySnafu.build())?,
+            Source::OnTheSpot(profile, kind) => {
+                // Mixing audio and sampling images would hold up the other requests
+                let service = self.clone();
+                tokio::task::spawn_blocking(move || service.challenge_on_the_spot(&profile, kind))
+                    .await
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))?
+            }
+        };
+        self.issue(challenge, &request, rounds)
+    }
 
-                    // If queue would block, take a moment to generate a thumbnail
-                    while self.queue.is_full() {
-                        if let Some(img_path) = self.thumbnail_queue.try_pop() {
-                            tracing::debug!(
-                                "Taking a moment to generate a thumbnail ({})",
-                                img_path.display()
-                            );
-                            match self.get_thumbnail(&img_path) {
-                                Err(err) => tracing::error!(
-                                    "Failed to generate thumbnail for {}: {:?}",
-                                    img_path.display(),
-                                    err
-                                ),
-                                _ => {}
-                            }
-                        } else {
-                            break;
-                        }
+    /// Whether an alternative kind can be made for a pool, as the audio clips and image
+    /// descriptions they are made from are optional.
+    fn offers(&self, kind: ChallengeKind, profile: &Profile) -> bool {
+        match kind {
+            ChallengeKind::Audio => self
+                .audio
+                .read()
+                .unwrap()
+                .iter()
+                .any(|collection| !collection.clips.is_empty()),
+            // Grids of descriptions need two collections, like grids of images
+            ChallengeKind::Text => {
+                let site = profile
+                    .site_key
+                    .as_deref()
+                    .and_then(|site_key| self.site(site_key));
+                self.collections
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|collection| {
+                        !collection.alt_texts.is_empty()
+                            && site
+                                .as_ref()
+                                .is_none_or(|site| site.allows_collection(&collection.name))
+                    })
+                    .count()
+                    >= 2
+            }
+            _ => true,
+        }
+    }
+
+    /// Makes a challenge which isn't served from a pool and registers its answer.
+    fn challenge_on_the_spot(&self, profile: &Profile, kind: ChallengeKind) -> Result<Challenge> {
+        let challenge = self.generate_kind(profile, kind)?;
+        self.add_pending(&challenge);
+        Ok(challenge)
+    }
+
+    /// Remembers the answer to a generated challenge until it is answered or expires.
+    fn add_pending(&self, challenge: &Challenge) {
+        self.answers.lock().unwrap().insert(
+            challenge.id.clone(),
+            PendingChallenge {
+                answer: challenge.answer.clone(),
+                topic: challenge.payload.topic().map(str::to_string),
+                site_key: challenge.site_key.clone(),
+                issued_at: None,
+                expires_at: None,
+                action: None,
+                cdata: None,
+                session: None,
+                image: None,
+                example: None,
+                tiles: HashMap::new(),
+                kind: challenge.kind(),
+                unlabelled: challenge.unlabelled.clone(),
+            },
+        );
+    }
+
+    /// Fills in a request continuing a session with the session's site, action and custom data.
+    fn resume_session(&self, request: &ChallengeRequest) -> Result<ChallengeRequest> {
+        let Some(session_id) = &request.session else {
+            return Ok(request.clone());
+        };
+
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(session_id)
+            .filter(|session| session.expires_at > SystemTime::now())
+            .context(UnknownSessionSnafu {
+                session: session_id,
+            })?;
+
+        Ok(ChallengeRequest {
+            site_key: session.site_key.clone(),
+            client: request.client.clone(),
+            action: session.action.clone(),
+            cdata: session.cdata.clone(),
+            session: Some(session_id.clone()),
+            kind: request.kind,
+            expires: request.expires,
+        })
+    }
+
+    /// Starts the expiry clock on a challenge that is being handed out when the request asks for
+    /// it, attaches the request's action and custom data, and starts or continues its
+    /// multi-round session. Sessions always expire, whichever API they are answered through.
+    ///
+    /// The session may have ended while waiting for a challenge, in which case the challenge is
+    /// dropped rather than handed out without it, as answering it would skip the other rounds.
+    fn issue(
+        &self,
+        mut challenge: Challenge,
+        request: &ChallengeRequest,
+        rounds: u32,
+    ) -> Result<Challenge> {
+        let now = SystemTime::now();
+        let expires_at = now + self.challenge_lifetime;
+        challenge.issued_at = Some(now);
+        challenge.expires_at = request.expires.then_some(expires_at);
+        Metrics::increment(&self.metrics.challenges_issued);
+        challenge.action = request.action.clone();
+        challenge.cdata = request.cdata.clone();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > now);
+        match &request.session {
+            Some(session_id) => {
+                let Some(session) = sessions.get_mut(session_id) else {
+                    drop(sessions);
+                    self.answers.lock().unwrap().remove(&challenge.id);
+                    return UnknownSessionSnafu {
+                        session: session_id,
                     }
+                    .fail();
+                };
+                session.current = Some(challenge.id.clone());
+                session.expires_at = expires_at;
+                challenge.session = Some(session_id.clone());
+                challenge.round = session.completed + 1;
+                challenge.rounds = session.rounds;
+            }
+            None if rounds > 1 => {
+                sessions.insert(
+                    challenge.id.clone(),
+                    Session {
+                        site_key: challenge.site_key.clone(),
+                        action: challenge.action.clone(),
+                        cdata: challenge.cdata.clone(),
+                        rounds,
+                        completed: 0,
+                        current: Some(challenge.id.clone()),
+                        expires_at,
+                    },
+                );
+                challenge.session = Some(challenge.id.clone());
+                challenge.rounds = rounds;
+            }
+            None => {}
+        }
+        drop(sessions);
 
-                    handle.block_on(self.queue.push(challenge));
+        let mut answers = self.answers.lock().unwrap();
+        answers.retain(|_, pending| !pending.is_expired(now));
+        if let Some(pending) = answers.get_mut(&challenge.id) {
+            pending.issued_at = Some(now);
+            pending.expires_at = challenge.expires_at;
+            pending.action = challenge.action.clone();
+            pending.cdata = challenge.cdata.clone();
+            pending.session = challenge.session.clone();
+        }
+
+        Ok(challenge)
+    }
+
+    /// Keeps the challenge image so it can be fetched separately by [`ImHumane::take_image`].
+    pub fn hold_image(&self, challenge_id: &str, image: Vec<u8>) {
+        if let Some(pending) = self.answers.lock().unwrap().get_mut(challenge_id) {
+            pending.image = Some(image);
+        }
+    }
+
+    pub fn take_image(&self, challenge_id: &str) -> Option<Vec<u8>> {
+        let mut answers = self.answers.lock().unwrap();
+        let pending = answers.get_mut(challenge_id)?;
+        if pending.is_expired(SystemTime::now()) {
+            return None;
+        }
+        pending.image.take()
+    }
+
+    /// Keeps the example image so it can be fetched separately by [`ImHumane::take_example`].
+    pub fn hold_example(&self, challenge_id: &str, example: Vec<u8>) {
+        if let Some(pending) = self.answers.lock().unwrap().get_mut(challenge_id) {
+            pending.example = Some(example);
+        }
+    }
+
+    pub fn take_example(&self, challenge_id: &str) -> Option<Vec<u8>> {
+        let mut answers = self.answers.lock().unwrap();
+        let pending = answers.get_mut(challenge_id)?;
+        if pending.is_expired(SystemTime::now()) {
+            return None;
+        }
+        pending.example.take()
+    }
+
+    /// Cuts a collage into its tiles and keeps each under a random key, so it can be fetched
+    /// separately by [`ImHumane::take_tile`]. Returns the keys in the order of the layout's tiles.
+    pub async fn hold_tiles(
+        &self,
+        challenge_id: &str,
+        collage: Vec<u8>,
+        layout: Layout,
+    ) -> Result<Vec<String>> {
+        // Decoding the collage and encoding every tile would hold up the other requests
+        let tiles = tokio::task::spawn_blocking(move || split_tiles(&collage, &layout))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))?;
+        let keys: Vec<_> = tiles
+            .iter()
+            .map(|_| uuid::Uuid::new_v4().simple().to_string())
+            .collect();
+        if let Some(pending) = self.answers.lock().unwrap().get_mut(challenge_id) {
+            pending.tiles = keys.iter().cloned().zip(tiles).collect();
+        }
+        Ok(keys)
+    }
+
+    pub fn take_tile(&self, challenge_id: &str, key: &str) -> Option<Vec<u8>> {
+        let mut answers = self.answers.lock().unwrap();
+        let pending = answers.get_mut(challenge_id)?;
+        if pending.is_expired(SystemTime::now()) {
+            return None;
+        }
+        pending.tiles.remove(key)
+    }
+
+    /// Checks an answer. Correct answers either issue a token, or when the challenge is part of
+    /// a multi-round session which isn't done yet, move the session on to the next round.
+    /// Any failure ends the session.
+    pub fn check_answer(
+        &self,
+        challenge_id: String,
+        answer: Answer,
+        client: &ClientInfo,
+    ) -> Result<AnswerOutcome> {
+        let pending =
+            self.answers
+                .lock()
+                .unwrap()
+                .remove(&challenge_id)
+                .context(UnknownChallengeSnafu {
+                    challenge_id: &challenge_id,
+                })?;
+
+        tracing::debug!(
+            answer = %answer,
+            challenge_id = challenge_id,
+            correct_answer = pending.answer,
+            "Checking answer",
+        );
+
+        // Taken out of the map so that it's only put back when the round succeeds
+        let session = match &pending.session {
+            Some(session_id) => {
+                let session = self.sessions.lock().unwrap().remove(session_id);
+                let session = session
+                    .filter(|session| session.current.as_ref() == Some(&challenge_id))
+                    .context(UnknownSessionSnafu {
+                        session: session_id,
+                    })?;
+                Some((session_id.clone(), session))
+            }
+            None => None,
+        };
+
+        let now = SystemTime::now();
+        ensure!(
+            !pending.is_expired(now),
+            ChallengeExpiredSnafu { challenge_id }
+        );
+        let correct = self
+            .generators
+            .get(&pending.kind)
+            .is_some_and(|generator| generator.check(&pending.answer, &answer));
+        self.risk.record_answer(client, correct);
+        self.attack.record_answer(correct);
+        Metrics::increment(match correct {
+            true => &self.metrics.answers_correct,
+            false => &self.metrics.answers_wrong,
+        });
+        ensure!(correct, WrongAnswerSnafu { challenge_id });
+
+        let site = pending
+            .site_key
+            .as_deref()
+            .and_then(|site_key| self.site(site_key));
+        let mut token_lifetime = site
+            .as_ref()
+            .and_then(|site| site.token_lifetime)
+            .map_or(self.token_lifetime, Duration::from_secs);
+        if self.attack.is_active() {
+            token_lifetime = token_lifetime.min(self.attack.policy().token_lifetime);
+        }
+
+        let solve_time = pending
+            .issued_at
+            .map(|issued_at| now.duration_since(issued_at).unwrap_or_default());
+        // Proof-of-work is solved by the browser, so its timing says nothing about a human
+        let human_solve_time = solve_time.filter(|_| pending.kind.is_visual());
+        let limits = site
+            .as_ref()
+            .map_or(self.solve_time, |site| self.solve_time.for_site(site));
+        let mut score = self.risk.score(client, human_solve_time);
+        if let Some(violation) = human_solve_time.and_then(|solve_time| limits.check(solve_time)) {
+            tracing::debug!(
+                challenge_id = challenge_id,
+                solve_time = ?solve_time,
+                violation = ?violation,
+                "Answer outside the solve time limits",
+            );
+            match (limits.policy, violation) {
+                (SolveTimePolicy::Reject, SolveTimeViolation::TooFast) => {
+                    return AnsweredTooFastSnafu { challenge_id }.fail()
+                }
+                (SolveTimePolicy::Reject, SolveTimeViolation::TooSlow) => {
+                    return AnsweredTooSlowSnafu { challenge_id }.fail()
                 }
-                Err(err) => {
-                    tracing::error!("Failed to generate challenge: {:#}", err);
-                    std::thread::sleep(Duration::from_secs(1));
+                (SolveTimePolicy::Flag, _) => score = 1.0,
+            }
+        }
+
+        // Only answers which pass are trusted to label images
+        if let (Some(topic), Answer::Text(answer)) = (&pending.topic, &answer) {
+            if !pending.unlabelled.is_empty() {
+                let selections = ungraded_selections(&pending.answer, answer);
+                self.labels
+                    .record(topic, pending.unlabelled.iter().zip(selections));
+            }
+        }
+
+        let token = match session {
+            Some((session_id, mut session)) => {
+                session.completed += 1;
+                if session.completed < session.rounds {
+                    let outcome = AnswerOutcome::NextRound {
+                        session: session_id.clone(),
+                        completed: session.completed,
+                        rounds: session.rounds,
+                    };
+                    session.current = None;
+                    session.expires_at = now + self.challenge_lifetime;
+                    self.sessions.lock().unwrap().insert(session_id, session);
+                    return Ok(outcome);
                 }
+                session_id
+            }
+            None => challenge_id,
+        };
+
+        let mut validated_tokens = self.validated_tokens.lock().unwrap();
+        validated_tokens.retain(|_, token| token.expires_at > now);
+        validated_tokens.insert(
+            token.clone(),
+            ValidatedToken {
+                site_key: pending.site_key,
+                kind: pending.kind,
+                topic: pending.topic,
+                validated_at: now,
+                solve_time,
+                expires_at: now + token_lifetime,
+                origin: client.origin.clone(),
+                ip: client.ip,
+                user_agent_fingerprint: client.user_agent_fingerprint(),
+                action: pending.action,
+                cdata: pending.cdata,
+                uses: 0,
+                max_uses: site.map_or(1, |site| site.max_uses.get()),
+                score: Some(score),
+            },
+        );
+        Ok(AnswerOutcome::Validated { token })
+    }
+
+    /// Redeems a token, consuming it once its site's use budget is spent.
+    pub fn check_token(
+        &self,
+        challenge_id: String,
+        request: &TokenRequest,
+    ) -> Result<ValidatedToken> {
+        let result = self.find_token(challenge_id, request, true);
+        Metrics::increment(match result.is_ok() {
+            true => &self.metrics.tokens_valid,
+            false => &self.metrics.tokens_invalid,
+        });
+        result
+    }
+
+    /// Redeems several tokens at once. Each token is checked as by [`ImHumane::check_token`].
+    pub fn check_tokens(
+        &self,
+        tokens: Vec<(String, TokenRequest)>,
+    ) -> Result<Vec<Result<ValidatedToken>>> {
+        ensure!(
+            tokens.len() <= self.max_batch_size,
+            BatchTooLargeSnafu {
+                size: tokens.len(),
+                limit: self.max_batch_size,
+            }
+        );
+
+        Ok(tokens
+            .into_iter()
+            .map(|(challenge_id, request)| self.check_token(challenge_id, &request))
+            .collect())
+    }
+
+    /// Checks a token like [`ImHumane::check_token`] without redeeming it.
+    pub fn peek_token(
+        &self,
+        challenge_id: String,
+        request: &TokenRequest,
+    ) -> Result<ValidatedToken> {
+        self.find_token(challenge_id, request, false)
+    }
+
+    /// Consumes a token without checking the client, so it can no longer be redeemed.
+    pub fn revoke_token(&self, challenge_id: String, site_key: Option<&str>) -> Result<()> {
+        let mut validated_tokens = self.validated_tokens.lock().unwrap();
+        match validated_tokens.get(&challenge_id) {
+            Some(token) if token.site_key.as_deref() == site_key => {
+                validated_tokens.remove(&challenge_id);
+                Ok(())
+            }
+            _ => InvalidTokenSnafu {
+                token: challenge_id,
             }
+            .fail(),
         }
     }
 
-    fn get_thumbnail(&self, img_path: &PathBuf) -> Result<DynamicImage> {
-        // Need to make sure that only one thread is generating the content of this thumbnail at a time.
-        let thumb_err = OpenThumbnailSnafu {
-            path: img_path.as_path(),
+    /// Looks up a token. Tokens issued for a site are only valid when the same site key is given,
+    /// which callers only do once the site's secret is checked, for the same client when the
+    /// site binds tokens to clients, and for the expected action. Tokens which fail these checks
+    /// are left alone for the rightful client to redeem.
+    fn find_token(
+        &self,
+        challenge_id: String,
+        request: &TokenRequest,
+        redeem: bool,
+    ) -> Result<ValidatedToken> {
+        let site = request
+            .site_key
+            .as_deref()
+            .and_then(|site_key| self.site(site_key));
+        let mut validated_tokens = self.validated_tokens.lock().unwrap();
+
+        let token = match validated_tokens.get_mut(&challenge_id) {
+            Some(token)
+                if token.site_key == request.site_key && token.expires_at > SystemTime::now() =>
+            {
+                token
+            }
+            _ => {
+                return InvalidTokenSnafu {
+                    token: challenge_id,
+                }
+                .fail()
+            }
         };
-        let thumb_path = get_thumbnail_path(img_path);
-        let locked_file = LockedFile::open_rw_no_truncate(thumb_path.clone()).context(thumb_err)?;
-        let mut file = &locked_file.file;
 
-        // Check if the written data is a valid thumbnail
-        if file.metadata().context(thumb_err)?.len() > 0 {
-            let reader = BufReader::new(file);
-            let img =
-                image::load(reader, THUMBNAIL_FORMAT).context(OpenImageSnafu::from(img_path))?;
-            if img.width() == self.image_size && img.height() == self.image_size {
-                tracing::trace!("Reusing saved thumbnail for {}", thumb_path.display());
-                return Ok(img);
+        if let Err(mismatch) = token.check_binding(site.as_ref(), request) {
+            if mismatch != Mismatch::Action {
+                self.risk.record_mismatch(token.ip);
             }
+            return BindingMismatchSnafu {
+                token: challenge_id,
+                mismatch,
+            }
+            .fail();
         }
 
-        // Otherwise create a new one
-        tracing::debug!("Generating thumbnail for {}", img_path.display());
-        file.seek(std::io::SeekFrom::Start(0)).context(thumb_err)?;
-        file.set_len(0).context(thumb_err)?;
+        if !redeem {
+            return Ok(token.clone());
+        }
 
-        let orig_img = image::open(img_path.clone()).context(OpenImageSnafu::from(img_path))?;
-        let orig_img = orig_img.resize(self.image_size, self.image_size, FilterType::Triangle);
-        orig_img
-            .save_with_format(thumb_path, THUMBNAIL_FORMAT)
-            .context(GenerateImageSnafu {})?;
+        token.uses += 1;
+        if token.remaining_uses() > 0 {
+            Ok(token.clone())
+        } else {
+            Ok(validated_tokens.remove(&challenge_id).unwrap())
+        }
+    }
 
-        Ok(orig_img)
+    /// Checks the bearer token of an admin API request.
+    pub fn check_admin_token(&self, token: Option<&str>) -> Result<()> {
+        let valid = match (&self.admin_token, token) {
+            (Some(expected), Some(token)) => secrets_match(expected, token),
+            _ => false,
+        };
+        ensure!(valid, InvalidAdminTokenSnafu);
+        Ok(())
     }
 
-    fn generate_image(&self, images: Vec<&(&PathBuf, u32)>) -> Result<Vec<u8>> {
-        // Assume a square grid
-        let img_area = self.image_size + self.gap_size;
-        let dimensions = (self.grid_length * img_area) + self.gap_size;
-        let mut imgbuf = RgbaImage::from_pixel(dimensions, dimensions, Rgba([0u8, 0u8, 0u8, 0u8]));
+    pub fn attack_status(&self) -> AttackStatus {
+        self.attack.status()
+    }
 
-        let mut i = 0;
-        for img in images {
-            tracing::trace!("Inserting {}", img.0.display());
-            let test_img = self.get_thumbnail(&img.0)?;
-            imgbuf
-                .copy_from(
-                    &test_img.to_rgba8(),
-                    self.gap_size + (img_area * (i % self.grid_length)),
-                    self.gap_size + (img_area * (i / self.grid_length)),
-                )
-                .context(GenerateImageSnafu {})?;
-            i += 1;
-        }
+    /// Switches attack mode on, for `duration` or until switched off, or off.
+    pub fn set_attack_mode(&self, enabled: bool, duration: Option<Duration>) -> AttackStatus {
+        self.attack.set(enabled, duration);
+        self.attack.status()
+    }
 
-        let mut data = Vec::new();
+    /// Renders the service metrics in the Prometheus text format.
+    pub fn render_metrics(&self) -> String {
+        let status = self.attack.status();
+        let mut writer = MetricsWriter::default();
+        writer
+            .counter(
+                "imhumane_challenges_issued_total",
+                "Challenges handed out to clients.",
+                &self.metrics.challenges_issued,
+            )
+            .family("imhumane_answers_total", "counter", "Answers checked.")
+            .sample(
+                "imhumane_answers_total",
+                &[("result", "correct")],
+                self.metrics.answers_correct.load(Ordering::Relaxed) as f64,
+            )
+            .sample(
+                "imhumane_answers_total",
+                &[("result", "wrong")],
+                self.metrics.answers_wrong.load(Ordering::Relaxed) as f64,
+            )
+            .family("imhumane_tokens_total", "counter", "Token redemptions.")
+            .sample(
+                "imhumane_tokens_total",
+                &[("result", "valid")],
+                self.metrics.tokens_valid.load(Ordering::Relaxed) as f64,
+            )
+            .sample(
+                "imhumane_tokens_total",
+                &[("result", "invalid")],
+                self.metrics.tokens_invalid.load(Ordering::Relaxed) as f64,
+            )
+            .counter(
+                "imhumane_rate_limited_total",
+                "Challenge requests refused by the rate limit.",
+                &self.metrics.rate_limited,
+            )
+            .family(
+                "imhumane_under_attack",
+                "gauge",
+                "Whether attack mode is on.",
+            )
+            .sample("imhumane_under_attack", &[], u8::from(status.active))
+            .family(
+                "imhumane_attack_mode_transitions_total",
+                "counter",
+                "Times attack mode was switched on or off.",
+            )
+            .sample(
+                "imhumane_attack_mode_transitions_total",
+                &[],
+                status.transitions as f64,
+            )
+            .family(
+                "imhumane_pool_challenges",
+                "gauge",
+                "Challenges ready to be handed out, per pool.",
+            );
+        for (profile, pool) in self.pools.read().unwrap().iter() {
+            let difficulty = format!("{:?}", profile.difficulty).to_lowercase();
+            writer.sample(
+                "imhumane_pool_challenges",
+                &[
+                    ("site", profile.site_key.as_deref().unwrap_or("")),
+                    ("difficulty", &difficulty),
+                ],
+                pool.len() as f64,
+            );
+        }
+        writer.finish()
+    }
 
-        let mut outbuf = Cursor::new(&mut data);
-        tracing::debug!("Generating image");
-        imgbuf
-            .write_to(&mut outbuf, ImageFormat::WebP)
-            .context(GenerateImageSnafu {})?;
+    /// Picks the pool with the most room, if any has room at all. Pools backing off after
+    /// failing are left out, so they don't keep the others from being refilled.
+    fn neediest_pool(&self) -> Option<(Profile, Pool)> {
+        let now = Instant::now();
+        // Locked after the pools, like when sites are loaded
+        let pools = self.pools.read().unwrap();
+        let backoff = self.backoff.lock().unwrap();
+        pools
+            .iter()
+            .filter(|(profile, pool)| {
+                !pool.is_full()
+                    && backoff
+                        .get(*profile)
+                        .is_none_or(|backoff| backoff.retry_at <= now)
+            })
+            .min_by_key(|(_, pool)| pool.len())
+            .map(|(profile, pool)| (profile.clone(), pool.clone()))
+    }
 
-        Ok(data)
+    /// Skips a pool for a while after its generation failed, returning for how long.
+    fn back_off(&self, profile: &Profile) -> Duration {
+        let now = Instant::now();
+        let mut backoff = self.backoff.lock().unwrap();
+        let backoff = backoff.entry(profile.clone()).or_insert(Backoff {
+            failures: 0,
+            retry_at: now,
+        });
+        let pause = GENERATOR_BACKOFF
+            .saturating_mul(1 << backoff.failures.min(16))
+            .min(MAX_GENERATOR_BACKOFF);
+        backoff.failures += 1;
+        backoff.retry_at = now + pause;
+        pause
     }
 
-    pub fn generate(&self) -> Result<Challenge> {
-        // Clone to free the lock
-        let collections = self.collections.read().unwrap().clone();
+    pub fn run_generator(&self) {
+        // This function relies on the limited capacity of the pools
+        // to limit the number of challenges generated.
+        loop {
+            if self.fill_neediest_pool() {
+                continue;
+            }
 
-        if collections.len() < 2 {
-            return (InsufficientCollectionsSnafu {}).fail();
+            // Every pool is full or backing off, take a moment to generate a thumbnail
+            if let Some(img_path) = self.thumbnail_queue.try_pop() {
+                tracing::debug!(
+                    "Taking a moment to generate a thumbnail ({})",
+                    img_path.display()
+                );
+                if let Err(err) = load_thumbnail(&img_path, self.image_size) {
+                    tracing::error!(
+                        "Failed to generate thumbnail for {}: {:?}",
+                        img_path.display(),
+                        err
+                    );
+                }
+            } else {
+                std::thread::sleep(GENERATOR_IDLE);
+            }
         }
+    }
 
-        let mut rng = thread_rng();
+    /// Generates a challenge for the neediest pool. Returns false when no pool needs one.
+    fn fill_neediest_pool(&self) -> bool {
+        let Some((profile, pool)) = self.neediest_pool() else {
+            return false;
+        };
+
+        let start = Instant::now();
+        match self.generate(&profile) {
+            Ok(challenge) => {
+                self.backoff.lock().unwrap().remove(&profile);
+                self.add_pending(&challenge);
+                tracing::debug!(
+                    time_ms = start.elapsed().as_millis(),
+                    challenge_id = challenge.id,
+                    challenge_kind = challenge.kind().as_str(),
+                    challenge_topic = challenge.payload.topic(),
+                    challenge_answer = challenge.answer,
+                    site_key = challenge.site_key,
+                    "Challenge generated.",
+                );
 
-        let num_collections = rng.gen_range(2..=std::cmp::min(collections.len(), 5));
+                // Another generator may have filled the pool in the meantime
+                if let Err(challenge) = pool.try_push(challenge) {
+                    self.answers.lock().unwrap().remove(&challenge.id);
+                }
+            }
+            Err(err) => {
+                let pause = self.back_off(&profile);
+                tracing::error!(
+                    site_key = profile.site_key,
+                    difficulty = ?profile.difficulty,
+                    "Failed to generate challenge, retrying in {}s: {:#}",
+                    pause.as_secs(),
+                    err
+                );
+            }
+        }
+        true
+    }
 
-        let mut sample = collections.choose_multiple(&mut rng, num_collections);
+    /// Makes a challenge for a pool, of a kind picked at random from those the site mixes in.
+    pub fn generate(&self, profile: &Profile) -> Result<Challenge> {
+        let kinds = self.kinds_for(profile);
+        let kind = kinds.choose(&mut thread_rng()).copied().unwrap_or_default();
+        self.generate_kind(profile, kind)
+    }
 
-        // The first entry of the sample will be our "correct" collection
-        let correct = sample.next().context(InsufficientCollectionsSnafu {})?;
+    /// Kinds of challenge mixed into a pool. Hard pools, which serve risky clients and attack
+    /// mode, only mix in kinds a person has to answer.
+    fn kinds_for(&self, profile: &Profile) -> Vec<ChallengeKind> {
+        let site = profile
+            .site_key
+            .as_deref()
+            .and_then(|site_key| self.site(site_key));
+        self.site_kinds(site.as_ref(), profile.difficulty)
+    }
 
-        // Weight correct answers with (num_collections)
-        let mut images: Vec<_> = correct
-            .images
+    /// Kinds of challenge mixed into the pool of a site (or requests without one) at
+    /// `difficulty`.
+    fn site_kinds(&self, site: Option<&Site>, difficulty: Difficulty) -> Vec<ChallengeKind> {
+        let kinds = match site {
+            Some(site) if !site.challenge_kinds.is_empty() => &site.challenge_kinds,
+            _ => &self.challenge_kinds,
+        };
+        kinds
             .iter()
-            .map(|img| (img, num_collections as u32))
-            .collect();
+            .copied()
+            .filter(|kind| difficulty < Difficulty::Hard || kind.is_visual())
+            .collect()
+    }
 
-        // Weight incorrect answers with 1
-        for collection in sample {
-            collection
-                .images
-                .iter()
-                .for_each(|img| images.push((img, 1)));
-        }
+    pub fn generate_kind(&self, profile: &Profile, kind: ChallengeKind) -> Result<Challenge> {
+        let site = match &profile.site_key {
+            Some(site_key) => Some(self.site(site_key).context(UnknownSiteSnafu { site_key })?),
+            None => None,
+        };
+        self.generate_for(profile, site.as_ref(), kind)
+    }
 
-        let question_images: Vec<_> = images
-            .choose_multiple_weighted(
-                &mut rng,
-                (self.grid_length * self.grid_length) as usize,
-                |(_, v)| *v,
-            )
+    /// Makes a challenge for a site, which need not be loaded yet.
+    fn generate_for(
+        &self,
+        profile: &Profile,
+        site: Option<&Site>,
+        kind: ChallengeKind,
+    ) -> Result<Challenge> {
+        let generator = self
+            .generators
+            .get(&kind)
+            .context(NoGeneratorSnafu { kind })?;
+
+        // Clone to free the lock
+        let collections: Vec<_> = self
+            .collections
+            .read()
             .unwrap()
+            .iter()
+            .filter(|collection| site.is_none_or(|site| site.allows_collection(&collection.name)))
+            .cloned()
             .collect();
+        let backgrounds = self.backgrounds.read().unwrap().clone();
+        let audio = self.audio.read().unwrap().clone();
+        let unlabelled = self.pick_unlabelled();
 
-        let answer = String::from_iter(question_images.iter().map(|(_, weight)| {
-            if *weight == num_collections as u32 {
-                '1'
-            } else {
-                '0'
-            }
-        }));
-
-        Ok(Challenge {
-            id: Uuid::new_v4().to_string(),
-            image: self.generate_image(question_images)?,
-            topic: correct.name.clone(),
+        let context = GeneratorContext {
+            profile,
+            collections: &collections,
+            backgrounds: &backgrounds,
+            audio: &audio,
+            unlabelled: unlabelled.as_ref(),
             image_size: self.image_size,
             gap_size: self.gap_size,
             grid_length: self.grid_length,
-            answer,
-        })
+            layout_style: site.and_then(|site| site.layout).unwrap_or(self.layout),
+            example_prompt: site
+                .and_then(|site| site.example_prompts)
+                .unwrap_or(self.example_prompts),
+            pow_bits: self.proof_of_work_policy(site).bits_for(profile.difficulty),
+        };
+        generator.generate(&context)
+    }
+
+    /// Picks an image of the unlabelled pool to show ungraded, as often as the label policy
+    /// asks for one. Images answers already agree on are left out.
+    fn pick_unlabelled(&self) -> Option<PathBuf> {
+        let mut rng = thread_rng();
+        if !rng.gen_bool(self.labels.policy().chance.clamp(0.0, 1.0)) {
+            return None;
+        }
+        let unlabelled = self.unlabelled.read().unwrap();
+        self.labels
+            .unsettled(&unlabelled)
+            .choose(&mut rng)
+            .map(|image| (*image).clone())
     }
 
     pub fn scan_for_collections(&self, root: &Path) -> Result<()> {
         let mut collections = Vec::new();
+        let mut backgrounds = Vec::new();
+        let mut unlabelled = Vec::new();
 
         for entry in root.read_dir().context(ScanSnafu::from(root))? {
             let entry = entry.context(ScanSnafu::from(root))?;
@@ -270,22 +1229,35 @@ impl ImHumane {
 
             // New collection
             if ftype.is_dir() {
+                // Backgrounds are loaded at collage size when needed, rather than as thumbnails
+                let is_backgrounds = entry.file_name() == BACKGROUNDS_COLLECTION;
+
                 // Scan for images
                 let mut images = Vec::new();
+                let mut alt_texts = HashMap::new();
                 for image in path.read_dir().context(ScanSnafu::from(path.as_path()))? {
                     let image = image.context(ScanSnafu::from(path.as_path()))?;
                     let img_path = image.path();
 
-                    if img_path.is_file()
-                        && !img_path
-                            .file_name()
-                            .unwrap()
-                            .to_string_lossy()
-                            .starts_with(THUMBNAIL_PREFIX)
+                    let file_name = img_path.file_name().unwrap().to_string_lossy();
+                    let is_sidecar = img_path
+                        .extension()
+                        .is_some_and(|extension| extension == IMAGE_METADATA_EXTENSION);
+                    if img_path.is_file() && is_sidecar && file_name != METADATA_FILE {
+                        let data = std::fs::read(&img_path)
+                            .context(ScanSnafu::from(img_path.as_path()))?;
+                        let metadata: ImageMetadata = serde_json::from_slice(&data)
+                            .context(ImageMetadataSnafu::from(img_path.as_path()))?;
+                        if let Some(alt) = metadata.alt.filter(|alt| !alt.trim().is_empty()) {
+                            alt_texts.insert(img_path.with_extension(""), alt);
+                        }
+                    } else if img_path.is_file()
+                        && !file_name.starts_with(THUMBNAIL_PREFIX)
+                        && file_name != METADATA_FILE
                     {
                         // Check if this image needs a thumbnail generated
                         let thumbnail = get_thumbnail_path(&img_path);
-                        if !thumbnail.exists() {
+                        if !is_backgrounds && !thumbnail.exists() {
                             tracing::debug!("{} added to thumbnail queue", img_path.display());
                             self.thumbnail_queue.push(img_path.clone());
                         }
@@ -294,7 +1266,16 @@ impl ImHumane {
                     }
                 }
 
-                if images.len() == 0 {
+                if images.is_empty() {
+                    continue;
+                }
+
+                if is_backgrounds {
+                    backgrounds = images;
+                    continue;
+                }
+                if entry.file_name() == UNLABELLED_COLLECTION {
+                    unlabelled = images;
                     continue;
                 }
 
@@ -306,10 +1287,25 @@ impl ImHumane {
                     }
                 };
 
+                let metadata_path = path.join(METADATA_FILE);
+                let metadata = if metadata_path.is_file() {
+                    let data = std::fs::read(&metadata_path)
+                        .context(ScanSnafu::from(metadata_path.as_path()))?;
+                    serde_json::from_slice(&data)
+                        .context(CollectionMetadataSnafu::from(metadata_path.as_path()))?
+                } else {
+                    CollectionMetadata::default()
+                };
+
+                // Sidecars of missing images describe nothing
+                alt_texts.retain(|image, _| images.contains(image));
+
                 collections.push(Collection {
                     // path,
                     name,
                     images,
+                    metadata,
+                    alt_texts,
                 });
             }
         }
@@ -317,6 +1313,146 @@ impl ImHumane {
         let mut existing_collections = self.collections.write().unwrap();
         existing_collections.clear();
         existing_collections.append(&mut collections);
+        *self.backgrounds.write().unwrap() = backgrounds;
+        *self.unlabelled.write().unwrap() = unlabelled;
+        self.labels.forget_missing();
+
+        Ok(())
+    }
+
+    /// Finds the words audio challenges read out: a directory per word, named as it should be
+    /// typed, holding WAV recordings of it.
+    pub fn scan_for_audio(&self, root: &Path) -> Result<()> {
+        let mut collections = Vec::new();
+
+        for entry in root.read_dir().context(ScanSnafu::from(root))? {
+            let entry = entry.context(ScanSnafu::from(root))?;
+            let path = entry.path();
+            let ftype = entry.file_type().context(ScanSnafu::from(path.as_path()))?;
+            if !ftype.is_dir() {
+                continue;
+            }
+
+            let mut clips = Vec::new();
+            for clip in path.read_dir().context(ScanSnafu::from(path.as_path()))? {
+                let clip_path = clip.context(ScanSnafu::from(path.as_path()))?.path();
+                let is_wav = clip_path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+                if !clip_path.is_file() || !is_wav {
+                    continue;
+                }
+                match read_clip(&clip_path) {
+                    Ok(samples) => clips.push(AudioClip {
+                        path: clip_path,
+                        samples,
+                    }),
+                    Err(err) => tracing::error!("Skipping audio clip: {}", err),
+                }
+            }
+            if clips.is_empty() {
+                continue;
+            }
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => {
+                    return CollectionNameSnafu::from(path.as_path()).fail();
+                }
+            };
+            tracing::debug!("Found {} clips of {name:?}", clips.len());
+            collections.push(AudioCollection { name, clips });
+        }
+
+        *self.audio.write().unwrap() = collections;
+
+        Ok(())
+    }
+
+    /// Loads the selections of unlabelled images from a JSON file, and keeps saving them there.
+    pub fn load_labels(&self, path: &Path) -> Result<()> {
+        self.labels.load(path)
+    }
+
+    /// Unlabelled images which answers agree on, with the collection proposed for each.
+    pub fn label_proposals(&self) -> Vec<LabelProposal> {
+        self.labels.proposals()
+    }
+
+    /// Makes a challenge of each kind a site mixes in at its difficulty, so a site whose
+    /// collections aren't enough is refused rather than failing in the generators forever.
+    fn check_site(&self, site: &Site) -> Result<()> {
+        let profile = Profile {
+            site_key: Some(site.site_key.clone()),
+            difficulty: site.difficulty,
+        };
+        let mut kinds = self.site_kinds(Some(site), site.difficulty);
+        if kinds.is_empty() {
+            kinds.push(ChallengeKind::default());
+        }
+        for kind in kinds {
+            self.generate_for(&profile, Some(site), kind)
+                .context(SiteCannotGenerateSnafu {
+                    site_key: &site.site_key,
+                    kind,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Loads site definitions from a JSON file containing a list of [`Site`]s,
+    /// replacing any previously loaded sites.
+    pub fn load_sites(&self, path: &Path) -> Result<()> {
+        let data = std::fs::read(path).context(ReadSitesSnafu::from(path))?;
+        let sites: Vec<Site> =
+            serde_json::from_slice(&data).context(ParseSitesSnafu::from(path))?;
+
+        let mut by_key = HashMap::new();
+        for site in sites {
+            ensure!(
+                !by_key.contains_key(&site.site_key),
+                DuplicateSiteSnafu {
+                    site_key: site.site_key
+                }
+            );
+            self.check_site(&site)?;
+            tracing::debug!("Loaded site {}", site.site_key);
+            by_key.insert(site.site_key.clone(), site);
+        }
+
+        // Give every site its own pools, keeping pools that are still in use
+        let mut pools = self.pools.write().unwrap();
+        let mut dropped = Vec::new();
+        pools.retain(|profile, pool| {
+            let keep = match &profile.site_key {
+                Some(site_key) => by_key.get(site_key).is_some_and(|site| {
+                    self.serves_difficulty(site.difficulty, profile.difficulty)
+                }),
+                None => true,
+            };
+            if !keep {
+                dropped.push(pool.clone());
+            }
+            keep
+        });
+        for site in by_key.values() {
+            self.add_pools(&mut pools, Some(&site.site_key), site.difficulty);
+        }
+        self.backoff
+            .lock()
+            .unwrap()
+            .retain(|profile, _| pools.contains_key(profile));
+
+        drop(pools);
+        *self.sites.write().unwrap() = by_key;
+
+        // Challenges waiting in dropped pools will never be handed out
+        let mut answers = self.answers.lock().unwrap();
+        for pool in dropped {
+            while let Some(challenge) = pool.try_pop() {
+                answers.remove(&challenge.id);
+            }
+        }
 
         Ok(())
     }
@@ -329,6 +1465,515 @@ impl From<&super::Config> for ImHumane {
             config.image_size,
             config.gap_size,
             config.grid_length,
+            config.layout,
+            config.example_prompts,
+            Duration::from_secs(config.challenge_lifetime),
+            Duration::from_secs(config.token_lifetime),
+            config.trusted_proxies.clone(),
+            config.max_batch_size,
+            config.solve_time_limits(),
+            config.rounds,
+            config.risk_scorer(),
+            config.adaptive_difficulty,
+            config.rate_limit,
+            config.attack_mode(),
+            config.admin_token.clone(),
+            config.proof_of_work_policy(),
+            config.challenge_kinds.clone(),
+            config.label_policy(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A service handing out proof-of-work challenges on the spot, which any answer solves, so
+    /// it works without images or generator threads.
+    fn service(config: serde_json::Value) -> ImHumane {
+        let mut base = serde_json::json!({
+            "buffer_size": 2,
+            "image_size": 8,
+            "gap_size": 1,
+            "grid_length": 3,
+            "pow_bits": 0,
+            "pow_max_risk": 1.0,
+        });
+        base.as_object_mut()
+            .unwrap()
+            .extend(config.as_object().unwrap().clone());
+        let config: crate::service::Config = serde_json::from_value(base).unwrap();
+        ImHumane::from(&config)
+    }
+
+    fn answer(service: &ImHumane, challenge: &Challenge) -> Result<AnswerOutcome> {
+        service.check_answer(
+            challenge.id.clone(),
+            Answer::Text(String::new()),
+            &ClientInfo::default(),
         )
     }
+
+    fn add_site(service: &ImHumane, site: serde_json::Value) -> Site {
+        let site: Site = serde_json::from_value(site).unwrap();
+        service
+            .sites
+            .write()
+            .unwrap()
+            .insert(site.site_key.clone(), site.clone());
+        site
+    }
+
+    /// Answers a challenge for `request`, returning the token.
+    fn solve(service: &ImHumane, request: &ChallengeRequest) -> String {
+        let challenge = service.try_get_challenge(request).unwrap().unwrap();
+        match answer(service, &challenge).unwrap() {
+            AnswerOutcome::Validated { token } => token,
+            outcome => panic!("expected a token, got {outcome:?}"),
+        }
+    }
+
+    #[test]
+    fn site_tokens_need_the_site_key() {
+        let service = service(serde_json::json!({}));
+        let site = add_site(
+            &service,
+            serde_json::json!({ "site_key": "site", "secret_key": "secret" }),
+        );
+        let token = solve(
+            &service,
+            &ChallengeRequest {
+                site_key: Some(site.site_key.clone()),
+                ..Default::default()
+            },
+        );
+
+        let anonymous = TokenRequest::default();
+        assert!(matches!(
+            service.check_token(token.clone(), &anonymous),
+            Err(Error::InvalidToken { .. })
+        ));
+        let authenticated = TokenRequest {
+            site_key: Some(site.site_key),
+            ..Default::default()
+        };
+        assert!(service.check_token(token, &authenticated).is_ok());
+    }
+
+    #[test]
+    fn secrets_find_their_site() {
+        let service = service(serde_json::json!({}));
+        add_site(
+            &service,
+            serde_json::json!({ "site_key": "site", "secret_key": "secret" }),
+        );
+        assert_eq!(service.site_for_secret("secret").unwrap().site_key, "site");
+        assert!(matches!(
+            service.site_for_secret("site"),
+            Err(Error::InvalidSecret)
+        ));
+    }
+
+    #[test]
+    fn admin_token_must_match() {
+        let enabled = service(serde_json::json!({ "admin_token": "token" }));
+        assert!(enabled.check_admin_token(Some("token")).is_ok());
+        assert!(enabled.check_admin_token(Some("toke")).is_err());
+        assert!(enabled.check_admin_token(None).is_err());
+
+        let disabled = service(serde_json::json!({}));
+        assert!(disabled.check_admin_token(Some("")).is_err());
+    }
+
+    #[test]
+    fn hard_pools_are_ready_for_attack_mode() {
+        let service = service(serde_json::json!({}));
+        service.set_attack_mode(true, None);
+        let (profile, _) = service.profile_for(&ChallengeRequest::default()).unwrap();
+        assert_eq!(profile.difficulty, Difficulty::Hard);
+        assert!(service.pool(&profile).is_ok());
+    }
+
+    #[test]
+    fn challenge_for_an_ended_session_is_not_issued() {
+        let service = service(serde_json::json!({ "rounds": 2 }));
+        let first = service
+            .try_get_challenge(&ChallengeRequest::default())
+            .unwrap()
+            .unwrap();
+        let AnswerOutcome::NextRound { session, .. } = answer(&service, &first).unwrap() else {
+            panic!("expected another round");
+        };
+
+        // The session ends, say by a wrong answer, while the next challenge is on its way
+        let request = ChallengeRequest {
+            session: Some(session.clone()),
+            ..Default::default()
+        };
+        let (request, source, rounds) = service.admit(&request).unwrap();
+        service.sessions.lock().unwrap().remove(&session);
+        let Source::OnTheSpot(profile, kind) = source else {
+            panic!("expected a challenge made on the spot");
+        };
+        let challenge = service.challenge_on_the_spot(&profile, kind).unwrap();
+        let challenge_id = challenge.id.clone();
+
+        let result = service.issue(challenge, &request, rounds);
+        assert!(matches!(result, Err(Error::UnknownSession { .. })));
+        assert!(!service.answers.lock().unwrap().contains_key(&challenge_id));
+    }
+
+    #[test]
+    fn site_tokens_are_redeemed_up_to_their_budget() {
+        let service = service(serde_json::json!({}));
+        let site = add_site(
+            &service,
+            serde_json::json!({ "site_key": "site", "secret_key": "secret", "max_uses": 2 }),
+        );
+        let request = ChallengeRequest {
+            site_key: Some(site.site_key.clone()),
+            ..Default::default()
+        };
+        let redeem = TokenRequest {
+            site_key: Some(site.site_key),
+            ..Default::default()
+        };
+
+        let token = solve(&service, &request);
+        let peeked = service.peek_token(token.clone(), &redeem).unwrap();
+        assert_eq!(peeked.remaining_uses(), 2);
+        let first = service.check_token(token.clone(), &redeem).unwrap();
+        assert_eq!(first.remaining_uses(), 1);
+        let second = service.check_token(token.clone(), &redeem).unwrap();
+        assert_eq!(second.remaining_uses(), 0);
+        assert!(service.check_token(token, &redeem).is_err());
+
+        let revoked = solve(&service, &request);
+        service.revoke_token(revoked.clone(), Some("site")).unwrap();
+        assert!(service.peek_token(revoked, &redeem).is_err());
+    }
+
+    #[test]
+    fn challenge_requests_are_rate_limited_per_ip() {
+        let service = service(serde_json::json!({ "rate_limit": 2, "attack_rate_limit": 1 }));
+        let request = |ip: [u8; 4]| ChallengeRequest {
+            client: ClientInfo {
+                ip: Some(IpAddr::from(ip)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            assert!(service.try_get_challenge(&request([192, 0, 2, 1])).is_ok());
+        }
+        assert!(matches!(
+            service.try_get_challenge(&request([192, 0, 2, 1])),
+            Err(Error::RateLimited { .. })
+        ));
+
+        // Attack mode tightens the limit
+        service.attack.set(true, None);
+        assert!(service.try_get_challenge(&request([192, 0, 2, 2])).is_ok());
+        assert!(matches!(
+            service.try_get_challenge(&request([192, 0, 2, 2])),
+            Err(Error::RateLimited { .. })
+        ));
+    }
+
+    #[test]
+    fn batches_redeem_each_token_in_turn() {
+        let service = service(serde_json::json!({ "max_batch_size": 3 }));
+        let site = add_site(
+            &service,
+            serde_json::json!({ "site_key": "site", "secret_key": "secret" }),
+        );
+        let request = ChallengeRequest {
+            site_key: Some(site.site_key.clone()),
+            ..Default::default()
+        };
+        let redeem = TokenRequest {
+            site_key: Some(site.site_key),
+            ..Default::default()
+        };
+        let token = solve(&service, &request);
+
+        let results = service
+            .check_tokens(vec![
+                (token.clone(), redeem.clone()),
+                (token, redeem.clone()),
+                ("unknown".to_string(), redeem.clone()),
+            ])
+            .unwrap();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::InvalidToken { .. })));
+        assert!(matches!(results[2], Err(Error::InvalidToken { .. })));
+    }
+
+    #[test]
+    fn batches_over_the_limit_are_refused() {
+        let service = service(serde_json::json!({ "max_batch_size": 1 }));
+        let token = solve(&service, &ChallengeRequest::default());
+        let redeem = TokenRequest::default();
+
+        assert!(matches!(
+            service.check_tokens(vec![(token.clone(), redeem.clone()); 2]),
+            Err(Error::BatchTooLarge { size: 2, limit: 1 })
+        ));
+        // Nothing in a refused batch is redeemed
+        assert!(service.check_token(token, &redeem).is_ok());
+    }
+
+    #[test]
+    fn sessions_need_every_round() {
+        let service = service(serde_json::json!({ "rounds": 3 }));
+        let mut request = ChallengeRequest {
+            action: Some("login".to_string()),
+            ..Default::default()
+        };
+
+        for round in 1..3 {
+            let challenge = service.try_get_challenge(&request).unwrap().unwrap();
+            let AnswerOutcome::NextRound {
+                session,
+                completed,
+                rounds,
+            } = answer(&service, &challenge).unwrap()
+            else {
+                panic!("expected another round");
+            };
+            assert_eq!((completed, rounds), (round, 3));
+            // Only the session carries the action on
+            request = ChallengeRequest {
+                session: Some(session),
+                ..Default::default()
+            };
+        }
+
+        let token = solve(&service, &request);
+        assert_eq!(Some(&token), request.session.as_ref());
+        let validated = service
+            .check_token(token.clone(), &TokenRequest::default())
+            .unwrap();
+        assert_eq!(validated.action.as_deref(), Some("login"));
+        assert!(matches!(
+            service.try_get_challenge(&request),
+            Err(Error::UnknownSession { .. })
+        ));
+    }
+
+    #[test]
+    fn only_v2_challenges_expire() {
+        let service = service(serde_json::json!({ "challenge_lifetime": 0 }));
+        let get = |expires| {
+            let request = ChallengeRequest {
+                expires,
+                ..Default::default()
+            };
+            service.try_get_challenge(&request).unwrap().unwrap()
+        };
+
+        let v1 = get(false);
+        assert_eq!(v1.expires_at, None);
+        assert!(answer(&service, &v1).is_ok());
+
+        let v2 = get(true);
+        assert!(v2.expires_at.is_some());
+        assert!(matches!(
+            answer(&service, &v2),
+            Err(Error::ChallengeExpired { .. })
+        ));
+    }
+
+    fn pool_len(service: &ImHumane, site_key: &str, difficulty: Difficulty) -> usize {
+        let profile = Profile {
+            site_key: Some(site_key.to_string()),
+            difficulty,
+        };
+        service.pool(&profile).unwrap().len()
+    }
+
+    #[test]
+    fn failing_pools_dont_starve_the_others() {
+        // Without images only proof-of-work challenges can be made
+        let service = service(serde_json::json!({ "challenge_kinds": ["proof_of_work"] }));
+        for site in [
+            serde_json::json!({ "site_key": "broken", "secret_key": "1", "challenge_kinds": ["grid"] }),
+            serde_json::json!({ "site_key": "healthy", "secret_key": "2" }),
+        ] {
+            let site = add_site(&service, site);
+            service.add_pools(
+                &mut service.pools.write().unwrap(),
+                Some(&site.site_key),
+                site.difficulty,
+            );
+        }
+
+        for _ in 0..20 {
+            service.fill_neediest_pool();
+        }
+        assert_eq!(pool_len(&service, "healthy", Difficulty::Normal), 2);
+        assert_eq!(pool_len(&service, "broken", Difficulty::Normal), 0);
+        assert!(service.backoff.lock().unwrap().contains_key(&Profile {
+            site_key: Some("broken".to_string()),
+            difficulty: Difficulty::Normal,
+        }));
+    }
+
+    #[test]
+    fn sites_which_cant_make_challenges_are_refused() {
+        let service = service(serde_json::json!({ "challenge_kinds": ["proof_of_work"] }));
+        let path =
+            std::env::temp_dir().join(format!("imhumane-sites-{}.json", uuid::Uuid::new_v4()));
+        let load = |sites: serde_json::Value| {
+            std::fs::write(&path, sites.to_string()).unwrap();
+            service.load_sites(&path)
+        };
+
+        let result = load(serde_json::json!([
+            { "site_key": "healthy", "secret_key": "1" },
+            { "site_key": "broken", "secret_key": "2", "challenge_kinds": ["grid"] },
+        ]));
+        assert!(matches!(
+            result,
+            Err(Error::SiteCannotGenerate { ref site_key, .. }) if site_key == "broken"
+        ));
+        assert!(service.site("healthy").is_none());
+
+        let result = load(serde_json::json!([{ "site_key": "healthy", "secret_key": "1" }]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+        assert!(service.site("healthy").is_some());
+    }
+
+    #[test]
+    fn dropped_pools_forget_their_answers() {
+        let service = service(serde_json::json!({ "challenge_kinds": ["proof_of_work"] }));
+        let path =
+            std::env::temp_dir().join(format!("imhumane-sites-{}.json", uuid::Uuid::new_v4()));
+        let load = |sites: serde_json::Value| {
+            std::fs::write(&path, sites.to_string()).unwrap();
+            service.load_sites(&path).unwrap();
+        };
+        let site_answers = || {
+            service
+                .answers
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|pending| pending.site_key.as_deref() == Some("site"))
+                .count()
+        };
+
+        load(serde_json::json!([{ "site_key": "site", "secret_key": "secret" }]));
+        while service.fill_neediest_pool() {}
+        assert!(site_answers() > 0);
+
+        load(serde_json::json!([]));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(site_answers(), 0);
+    }
+
+    #[test]
+    fn unavailable_alternatives_are_not_offered() {
+        let service = service(serde_json::json!({}));
+        for kind in [ChallengeKind::Audio, ChallengeKind::Text] {
+            let request = ChallengeRequest {
+                kind: Some(kind),
+                ..Default::default()
+            };
+            assert!(matches!(
+                service.try_get_challenge(&request),
+                Err(Error::KindNotOffered { .. })
+            ));
+        }
+    }
+
+    /// A challenge handed out through `v2`, which expires.
+    fn v2_challenge(service: &ImHumane) -> Challenge {
+        let request = ChallengeRequest {
+            expires: true,
+            ..Default::default()
+        };
+        service.try_get_challenge(&request).unwrap().unwrap()
+    }
+
+    /// Holds the tiles of a small collage for `challenge`, returning their keys.
+    async fn hold_tiles(service: &ImHumane, challenge: &Challenge) -> Vec<String> {
+        let layout = Layout::grid(2, 2, 4, 1);
+        let mut collage = Vec::new();
+        image::RgbaImage::new(layout.width, layout.height)
+            .write_to(
+                &mut std::io::Cursor::new(&mut collage),
+                image::ImageFormat::WebP,
+            )
+            .unwrap();
+        service
+            .hold_tiles(&challenge.id, collage, layout)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn tiles_can_be_fetched_once() {
+        let service = service(serde_json::json!({}));
+        let challenge = v2_challenge(&service);
+        let keys = hold_tiles(&service, &challenge).await;
+        assert_eq!(keys.len(), 4);
+
+        for key in &keys {
+            assert!(service.take_tile(&challenge.id, key).is_some());
+            assert!(service.take_tile(&challenge.id, key).is_none());
+        }
+        assert!(service.take_tile(&challenge.id, "unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn tiles_expire_with_the_challenge() {
+        let service = service(serde_json::json!({ "challenge_lifetime": 0 }));
+        let challenge = v2_challenge(&service);
+        let keys = hold_tiles(&service, &challenge).await;
+
+        assert!(service.take_tile(&challenge.id, &keys[0]).is_none());
+    }
+
+    #[tokio::test]
+    async fn tiles_are_dropped_once_answered() {
+        let service = service(serde_json::json!({}));
+        let challenge = v2_challenge(&service);
+        let keys = hold_tiles(&service, &challenge).await;
+
+        assert!(answer(&service, &challenge).is_ok());
+        assert!(service.take_tile(&challenge.id, &keys[0]).is_none());
+    }
+
+    #[test]
+    fn examples_can_be_fetched_once() {
+        let service = service(serde_json::json!({}));
+        let challenge = v2_challenge(&service);
+        service.hold_example(&challenge.id, vec![1, 2, 3]);
+
+        assert_eq!(service.take_example(&challenge.id), Some(vec![1, 2, 3]));
+        assert_eq!(service.take_example(&challenge.id), None);
+    }
+
+    #[test]
+    fn examples_expire_with_the_challenge() {
+        let service = service(serde_json::json!({ "challenge_lifetime": 0 }));
+        let challenge = v2_challenge(&service);
+        service.hold_example(&challenge.id, vec![1, 2, 3]);
+
+        assert!(service.take_example(&challenge.id).is_none());
+    }
+
+    #[test]
+    fn examples_are_dropped_once_answered() {
+        let service = service(serde_json::json!({}));
+        let challenge = v2_challenge(&service);
+        service.hold_example(&challenge.id, vec![1, 2, 3]);
+
+        assert!(answer(&service, &challenge).is_ok());
+        assert!(service.take_example(&challenge.id).is_none());
+    }
 }
@@ -0,0 +1,132 @@
+use sha2::{Digest, Sha256};
+use std::num::NonZeroU32;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Site {
+    /// Public key embedded in pages to request challenges.
+    pub site_key: String,
+
+    /// Private key used by backends to verify tokens.
+    pub secret_key: String,
+
+    /// Origins allowed to request challenges. Any origin when empty.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    /// Collections challenges are built from. All collections when empty.
+    #[serde(default)]
+    pub allowed_collections: Vec<String>,
+
+    #[serde(default)]
+    pub difficulty: super::Difficulty,
+
+    /// Seconds a validated token can be redeemed for. Uses the global setting when unset.
+    #[serde(default)]
+    pub token_lifetime: Option<u64>,
+
+    /// Fastest a challenge can be answered, in milliseconds. Uses the global setting when unset.
+    #[serde(default)]
+    pub min_solve_time_ms: Option<u64>,
+
+    /// Slowest a challenge can be answered, in milliseconds. Uses the global setting when unset.
+    #[serde(default)]
+    pub max_solve_time_ms: Option<u64>,
+
+    /// Correct answers in a row needed for a token. Uses the global setting when unset.
+    #[serde(default)]
+    pub rounds: Option<u32>,
+
+    /// Kinds of challenge mixed into the site's pools. Uses the global setting when empty.
+    #[serde(default)]
+    pub challenge_kinds: Vec<super::ChallengeKind>,
+
+    /// How collages arrange their tiles. Uses the global setting when unset.
+    #[serde(default)]
+    pub layout: Option<super::LayoutStyle>,
+
+    /// Show an example image of the topic rather than its name. Uses the global setting when
+    /// unset.
+    #[serde(default)]
+    pub example_prompts: Option<bool>,
+
+    /// Leading zero bits asked of proof-of-work challenges. Uses the global setting when unset.
+    #[serde(default)]
+    pub pow_bits: Option<u32>,
+
+    /// Clients with a risk score up to this get proof-of-work challenges instead of images.
+    /// Uses the global setting when unset.
+    #[serde(default)]
+    pub pow_max_risk: Option<f32>,
+
+    /// Number of times a token can be redeemed before it is consumed, at least 1.
+    #[serde(default = "default_max_uses")]
+    pub max_uses: NonZeroU32,
+
+    /// Require tokens to be redeemed for the same IP address they were solved from.
+    #[serde(default)]
+    pub bind_ip: bool,
+
+    /// Require tokens to be redeemed for the same user agent they were solved with.
+    #[serde(default)]
+    pub bind_user_agent: bool,
+
+    /// Require tokens to be redeemed from the same origin they were solved on.
+    #[serde(default)]
+    pub bind_origin: bool,
+}
+
+fn default_max_uses() -> NonZeroU32 {
+    NonZeroU32::MIN
+}
+
+impl Site {
+    /// Compares a secret key without giving away how much of it matched.
+    pub fn has_secret(&self, secret_key: &str) -> bool {
+        secrets_match(&self.secret_key, secret_key)
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.is_empty() || self.allowed_origins.iter().any(|o| o == origin)
+    }
+
+    pub fn allows_collection(&self, name: &str) -> bool {
+        self.allowed_collections.is_empty() || self.allowed_collections.iter().any(|c| c == name)
+    }
+}
+
+/// Compares secrets in constant time. Hashing first evens out their lengths.
+pub(crate) fn secrets_match(expected: &str, given: &str) -> bool {
+    let expected = Sha256::digest(expected);
+    let given = Sha256::digest(given);
+    expected
+        .iter()
+        .zip(given.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_match_only_themselves() {
+        assert!(secrets_match("secret", "secret"));
+        assert!(!secrets_match("secret", "secreT"));
+        assert!(!secrets_match("secret", "secret "));
+        assert!(!secrets_match("secret", ""));
+    }
+
+    #[test]
+    fn tokens_are_redeemed_at_least_once() {
+        let site = |max_uses: serde_json::Value| {
+            serde_json::from_value::<Site>(serde_json::json!({
+                "site_key": "site",
+                "secret_key": "secret",
+                "max_uses": max_uses,
+            }))
+        };
+        assert_eq!(site(serde_json::json!(2)).unwrap().max_uses.get(), 2);
+        assert!(site(serde_json::json!(0)).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use super::Site;
+
+/// What to do with a correct answer which arrived outside the solve time limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolveTimePolicy {
+    /// Fail the answer.
+    #[default]
+    Reject,
+    /// Accept the answer, but give the token the most hostile score.
+    Flag,
+}
+
+/// How an answer's solve time fell outside the limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveTimeViolation {
+    TooFast,
+    TooSlow,
+}
+
+/// Bounds on the time between handing a challenge out and receiving its answer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SolveTimeLimits {
+    pub min: Duration,
+    pub max: Option<Duration>,
+    pub policy: SolveTimePolicy,
+}
+
+impl SolveTimeLimits {
+    /// Applies a site's overrides.
+    pub fn for_site(mut self, site: &Site) -> Self {
+        if let Some(min) = site.min_solve_time_ms {
+            self.min = Duration::from_millis(min);
+        }
+        if let Some(max) = site.max_solve_time_ms {
+            self.max = Some(Duration::from_millis(max));
+        }
+        self
+    }
+
+    pub fn check(&self, solve_time: Duration) -> Option<SolveTimeViolation> {
+        if solve_time < self.min {
+            Some(SolveTimeViolation::TooFast)
+        } else if self.max.is_some_and(|max| solve_time > max) {
+            Some(SolveTimeViolation::TooSlow)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(min: u64, max: Option<u64>) -> SolveTimeLimits {
+        SolveTimeLimits {
+            min: Duration::from_millis(min),
+            max: max.map(Duration::from_millis),
+            policy: SolveTimePolicy::Reject,
+        }
+    }
+
+    #[test]
+    fn answers_within_the_limits_pass() {
+        let limits = limits(500, Some(10_000));
+        assert_eq!(limits.check(Duration::from_millis(500)), None);
+        assert_eq!(limits.check(Duration::from_millis(10_000)), None);
+        assert_eq!(
+            limits.check(Duration::from_millis(499)),
+            Some(SolveTimeViolation::TooFast)
+        );
+        assert_eq!(
+            limits.check(Duration::from_millis(10_001)),
+            Some(SolveTimeViolation::TooSlow)
+        );
+    }
+
+    #[test]
+    fn answers_are_never_too_slow_without_a_maximum() {
+        assert_eq!(limits(0, None).check(Duration::from_secs(86_400)), None);
+    }
+
+    #[test]
+    fn sites_override_the_limits_they_set() {
+        let site: Site = serde_json::from_value(serde_json::json!({
+            "site_key": "site",
+            "secret_key": "secret",
+            "max_solve_time_ms": 2_000,
+        }))
+        .unwrap();
+
+        let limits = limits(500, None).for_site(&site);
+        assert_eq!(limits.min, Duration::from_millis(500));
+        assert_eq!(limits.max, Some(Duration::from_millis(2_000)));
+    }
+}
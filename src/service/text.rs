@@ -0,0 +1,150 @@
+use rand::prelude::*;
+use std::collections::HashMap;
+
+use super::{
+    collection::Collection,
+    error::*,
+    generator::{ChallengeGenerator, GeneratorContext},
+    grid::{answer_bits, sample_grid},
+    Challenge, ChallengeKind, ChallengePayload,
+};
+
+/// The descriptions of a grid's images as a list, for screen readers. Images are sampled as
+/// by [`GridGenerator`](super::GridGenerator), from those with a description, and the answer
+/// is in the same format, one `0` or `1` per description.
+#[derive(Debug, Default)]
+pub struct TextGenerator;
+
+impl ChallengeGenerator for TextGenerator {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Text
+    }
+
+    fn generate(&self, context: &GeneratorContext) -> Result<Challenge, Error> {
+        let mut rng = thread_rng();
+
+        // Only images with a description can be listed
+        let collections: Vec<_> = context
+            .collections
+            .iter()
+            .map(|collection| Collection {
+                images: collection
+                    .images
+                    .iter()
+                    .filter(|img| collection.alt_texts.contains_key(*img))
+                    .cloned()
+                    .collect(),
+                ..collection.clone()
+            })
+            .filter(|collection| !collection.images.is_empty())
+            .collect();
+        let alt_texts: HashMap<_, _> = collections
+            .iter()
+            .flat_map(|collection| collection.alt_texts.iter())
+            .collect();
+
+        let difficulty = context.profile.difficulty;
+        let count = difficulty.grid_length(context.grid_length).pow(2) as usize;
+        let sample = sample_grid(&mut rng, &collections, difficulty, count, false)?;
+
+        let answer = answer_bits(sample.images.iter().map(|(_, correct)| *correct));
+
+        let payload = ChallengePayload::Text {
+            topic: sample.topic.name.clone(),
+            descriptions: sample
+                .images
+                .iter()
+                .map(|(img, _)| alt_texts[img].clone())
+                .collect(),
+        };
+        Ok(Challenge::new(
+            payload,
+            answer,
+            difficulty,
+            context.profile.site_key.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{Difficulty, LayoutStyle, Profile};
+    use std::path::PathBuf;
+
+    /// A collection of `images` images, of which the first `described` have a description
+    /// naming the collection.
+    fn collection(name: &str, images: usize, described: usize) -> Collection {
+        let images: Vec<_> = (0..images)
+            .map(|i| PathBuf::from(format!("{name}/{i}.png")))
+            .collect();
+        Collection {
+            name: name.to_string(),
+            alt_texts: images
+                .iter()
+                .take(described)
+                .map(|img| (img.clone(), format!("{name}: {}", img.display())))
+                .collect(),
+            images,
+            metadata: Default::default(),
+        }
+    }
+
+    fn generate(collections: &[Collection]) -> Result<Challenge, Error> {
+        let profile = Profile {
+            site_key: None,
+            difficulty: Difficulty::Normal,
+        };
+        TextGenerator.generate(&GeneratorContext {
+            profile: &profile,
+            collections,
+            backgrounds: &[],
+            audio: &[],
+            unlabelled: None,
+            image_size: 8,
+            gap_size: 1,
+            grid_length: 3,
+            layout_style: LayoutStyle::default(),
+            example_prompt: false,
+            pow_bits: 0,
+        })
+    }
+
+    #[test]
+    fn answer_bits_mark_descriptions_of_the_topic() {
+        let collections = [
+            collection("cats", 20, 10),
+            collection("dogs", 20, 10),
+            collection("owls", 20, 10),
+        ];
+        for _ in 0..20 {
+            let challenge = generate(&collections).unwrap();
+            let ChallengePayload::Text {
+                topic,
+                descriptions,
+            } = challenge.payload
+            else {
+                panic!("Expected a text challenge, got {:?}", challenge.payload);
+            };
+
+            assert_eq!(descriptions.len(), 9);
+            assert_eq!(challenge.answer.len(), descriptions.len());
+            for (description, bit) in descriptions.iter().zip(challenge.answer.chars()) {
+                let (name, img) = description.split_once(": ").unwrap();
+                assert_eq!(bit == '1', name == topic, "{description} for {topic}");
+                // Only described images are listed
+                let i: usize = img.split(['/', '.']).nth(1).unwrap().parse().unwrap();
+                assert!(i < 10, "{img} has no description");
+            }
+        }
+    }
+
+    #[test]
+    fn collections_without_descriptions_are_left_out() {
+        let collections = [collection("cats", 20, 20), collection("dogs", 20, 0)];
+        assert!(matches!(
+            generate(&collections),
+            Err(Error::InsufficientCollections)
+        ));
+    }
+}
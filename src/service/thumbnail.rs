@@ -0,0 +1,54 @@
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use snafu::prelude::*;
+use std::{
+    ffi::OsString,
+    io::{BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+use super::{error::*, locked_file::LockedFile};
+
+pub(super) const THUMBNAIL_PREFIX: &str = ".thumbnail.";
+const THUMBNAIL_FORMAT: ImageFormat = ImageFormat::WebP;
+
+pub(super) fn get_thumbnail_path(img_path: &Path) -> PathBuf {
+    // Fancy filename gen to avoid an unnecessary conversion to str
+    let mut thumbnail = OsString::from(THUMBNAIL_PREFIX);
+    thumbnail.push(img_path.file_stem().unwrap());
+    thumbnail.push(".webp");
+    img_path.with_file_name(thumbnail)
+}
+
+/// Loads an image resized to `size`, reusing its saved thumbnail when it has the right size.
+pub(super) fn load_thumbnail(img_path: &PathBuf, size: u32) -> Result<DynamicImage, Error> {
+    // Need to make sure that only one thread is generating the content of this thumbnail at a time.
+    let thumb_err = OpenThumbnailSnafu {
+        path: img_path.as_path(),
+    };
+    let thumb_path = get_thumbnail_path(img_path);
+    let locked_file = LockedFile::open_rw_no_truncate(thumb_path.clone()).context(thumb_err)?;
+    let mut file = &locked_file.file;
+
+    // Check if the written data is a valid thumbnail
+    if file.metadata().context(thumb_err)?.len() > 0 {
+        let reader = BufReader::new(file);
+        let img = image::load(reader, THUMBNAIL_FORMAT).context(OpenImageSnafu::from(img_path))?;
+        if img.width() == size && img.height() == size {
+            tracing::trace!("Reusing saved thumbnail for {}", thumb_path.display());
+            return Ok(img);
+        }
+    }
+
+    // Otherwise create a new one
+    tracing::debug!("Generating thumbnail for {}", img_path.display());
+    file.seek(std::io::SeekFrom::Start(0)).context(thumb_err)?;
+    file.set_len(0).context(thumb_err)?;
+
+    let orig_img = image::open(img_path.clone()).context(OpenImageSnafu::from(img_path))?;
+    let orig_img = orig_img.resize(size, size, FilterType::Triangle);
+    orig_img
+        .save_with_format(thumb_path, THUMBNAIL_FORMAT)
+        .context(GenerateImageSnafu {})?;
+
+    Ok(orig_img)
+}
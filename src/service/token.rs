@@ -0,0 +1,253 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use super::{ChallengeKind, ClientInfo, Site};
+
+/// Which part of a token binding did not match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    Ip,
+    UserAgent,
+    Origin,
+    Action,
+}
+
+/// Describes who is redeeming a token, and what for.
+#[derive(Debug, Clone, Default)]
+pub struct TokenRequest {
+    /// Site the token must have been issued for, once authenticated by its secret. Tokens
+    /// issued for a site can't be redeemed without it.
+    pub site_key: Option<String>,
+    /// Client the token is redeemed for.
+    pub client: ClientInfo,
+    /// Action the token must have been issued for, if any.
+    pub action: Option<String>,
+}
+
+/// A token issued for a correctly answered challenge.
+#[derive(Debug, Clone)]
+pub struct ValidatedToken {
+    pub site_key: Option<String>,
+    pub kind: ChallengeKind,
+    /// Topic of the challenge, for kinds which have one.
+    pub topic: Option<String>,
+    /// When the challenge was answered.
+    pub validated_at: SystemTime,
+    /// Time between handing the challenge out and receiving its answer.
+    pub solve_time: Option<Duration>,
+    pub expires_at: SystemTime,
+    /// `Origin` of the page the challenge was answered on.
+    pub origin: Option<String>,
+    /// IP address the challenge was answered from.
+    pub ip: Option<IpAddr>,
+    pub user_agent_fingerprint: Option<u64>,
+    pub action: Option<String>,
+    pub cdata: Option<String>,
+    /// Times the token has been redeemed.
+    pub uses: u32,
+    /// Times the token can be redeemed before it is consumed.
+    pub max_uses: u32,
+    /// Risk score between 0 (benign) and 1 (hostile), when scoring is available.
+    pub score: Option<f32>,
+}
+
+impl ValidatedToken {
+    /// Compares the client a token is redeemed for against the one which solved it,
+    /// for each binding the site requires, and the action when one is expected.
+    pub fn check_binding(
+        &self,
+        site: Option<&Site>,
+        request: &TokenRequest,
+    ) -> Result<(), Mismatch> {
+        if request
+            .action
+            .as_ref()
+            .is_some_and(|action| self.action.as_ref() != Some(action))
+        {
+            return Err(Mismatch::Action);
+        }
+
+        let Some(site) = site else {
+            return Ok(());
+        };
+        let client = &request.client;
+        if site.bind_ip && self.ip != client.ip {
+            return Err(Mismatch::Ip);
+        }
+        if site.bind_user_agent && self.user_agent_fingerprint != client.user_agent_fingerprint() {
+            return Err(Mismatch::UserAgent);
+        }
+        if site.bind_origin && self.origin != client.origin {
+            return Err(Mismatch::Origin);
+        }
+        Ok(())
+    }
+
+    pub fn remaining_uses(&self) -> u32 {
+        self.max_uses.saturating_sub(self.uses)
+    }
+
+    /// Host part of [`ValidatedToken::origin`].
+    pub fn hostname(&self) -> Option<&str> {
+        let origin = self.origin.as_deref()?;
+        let host = origin.split_once("://").map_or(origin, |(_, rest)| rest);
+        let host = host.split('/').next().unwrap_or(host);
+        // Keep IPv6 literals intact while dropping the port
+        Some(match host.rsplit_once(':') {
+            Some((name, port))
+                if !name.ends_with(':') && port.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                name
+            }
+            _ => host,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(origin: Option<&str>) -> ValidatedToken {
+        ValidatedToken {
+            site_key: None,
+            kind: ChallengeKind::default(),
+            topic: None,
+            validated_at: SystemTime::now(),
+            solve_time: None,
+            expires_at: SystemTime::now(),
+            origin: origin.map(str::to_string),
+            ip: None,
+            user_agent_fingerprint: None,
+            action: None,
+            cdata: None,
+            uses: 0,
+            max_uses: 1,
+            score: None,
+        }
+    }
+
+    fn client(ip: &str, user_agent: &str, origin: &str) -> ClientInfo {
+        ClientInfo {
+            origin: Some(origin.to_string()),
+            ip: Some(ip.parse().unwrap()),
+            user_agent: Some(user_agent.to_string()),
+            accept_language: None,
+        }
+    }
+
+    /// A token solved by `client` for the `login` action.
+    fn solved_by(client: &ClientInfo) -> ValidatedToken {
+        ValidatedToken {
+            ip: client.ip,
+            user_agent_fingerprint: client.user_agent_fingerprint(),
+            action: Some("login".to_string()),
+            ..token(client.origin.as_deref())
+        }
+    }
+
+    fn site(bindings: serde_json::Value) -> Site {
+        let mut site = serde_json::json!({ "site_key": "site", "secret_key": "secret" });
+        site.as_object_mut()
+            .unwrap()
+            .extend(bindings.as_object().unwrap().clone());
+        serde_json::from_value(site).unwrap()
+    }
+
+    #[test]
+    fn hostname_drops_scheme_port_and_path() {
+        let hostname = |origin| token(Some(origin)).hostname().map(str::to_string);
+        assert_eq!(hostname("https://example.com"), Some("example.com".into()));
+        assert_eq!(
+            hostname("https://example.com:8443"),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            hostname("http://example.com/path"),
+            Some("example.com".into())
+        );
+        assert_eq!(hostname("example.com:80"), Some("example.com".into()));
+        assert_eq!(hostname("http://[::1]:8080"), Some("[::1]".into()));
+        assert_eq!(hostname("http://[::1]"), Some("[::1]".into()));
+        assert_eq!(token(None).hostname(), None);
+    }
+
+    #[test]
+    fn bindings_are_only_checked_when_required() {
+        let solver = client("192.0.2.1", "Firefox", "https://example.com");
+        let token = solved_by(&solver);
+        let other = TokenRequest {
+            client: client("192.0.2.2", "Chrome", "https://example.org"),
+            ..Default::default()
+        };
+
+        assert_eq!(token.check_binding(None, &other), Ok(()));
+        assert_eq!(
+            token.check_binding(Some(&site(serde_json::json!({}))), &other),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn bindings_must_match_the_solver() {
+        let solver = client("192.0.2.1", "Firefox", "https://example.com");
+        let token = solved_by(&solver);
+        let site = site(serde_json::json!({
+            "bind_ip": true,
+            "bind_user_agent": true,
+            "bind_origin": true,
+        }));
+        let redeemed_for = |client| TokenRequest {
+            client,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            token.check_binding(Some(&site), &redeemed_for(solver.clone())),
+            Ok(())
+        );
+        assert_eq!(
+            token.check_binding(
+                Some(&site),
+                &redeemed_for(client("192.0.2.2", "Firefox", "https://example.com"))
+            ),
+            Err(Mismatch::Ip)
+        );
+        assert_eq!(
+            token.check_binding(
+                Some(&site),
+                &redeemed_for(client("192.0.2.1", "Chrome", "https://example.com"))
+            ),
+            Err(Mismatch::UserAgent)
+        );
+        assert_eq!(
+            token.check_binding(
+                Some(&site),
+                &redeemed_for(client("192.0.2.1", "Firefox", "https://example.org"))
+            ),
+            Err(Mismatch::Origin)
+        );
+    }
+
+    #[test]
+    fn expected_actions_must_match() {
+        let solver = ClientInfo::default();
+        let token = solved_by(&solver);
+        let for_action = |action: Option<&str>| TokenRequest {
+            action: action.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert_eq!(token.check_binding(None, &for_action(None)), Ok(()));
+        assert_eq!(
+            token.check_binding(None, &for_action(Some("login"))),
+            Ok(())
+        );
+        assert_eq!(
+            token.check_binding(None, &for_action(Some("signup"))),
+            Err(Mismatch::Action)
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use imhumane::http::{openapi::ApiDoc, route_methods};
+use utoipa::OpenApi;
+
+/// Converts an axum path (`/tokens/:id`) to OpenAPI syntax (`/tokens/{id}`).
+fn to_openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => format!("{{{param}}}"),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Routes as lowercase method and OpenAPI path pairs, such as `("get", "/tokens/{id}")`.
+fn routes() -> Vec<(String, String)> {
+    route_methods()
+        .into_iter()
+        .map(|(method, path)| (method.as_str().to_lowercase(), to_openapi_path(path)))
+        .collect()
+}
+
+/// Described operations as lowercase method and path pairs.
+fn operations() -> Vec<(String, String)> {
+    ApiDoc::openapi()
+        .paths
+        .paths
+        .into_iter()
+        .flat_map(|(path, item)| {
+            item.operations.into_keys().map(move |method| {
+                let method = serde_json::to_value(method).unwrap();
+                (method.as_str().unwrap().to_string(), path.clone())
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn every_route_is_described() {
+    let operations = operations();
+
+    let undescribed: Vec<_> = routes()
+        .into_iter()
+        .filter(|route| !operations.contains(route))
+        .collect();
+
+    assert!(
+        undescribed.is_empty(),
+        "Routes missing from the OpenAPI description: {undescribed:?}"
+    );
+}
+
+#[test]
+fn every_description_is_routed() {
+    let routes = routes();
+
+    let unrouted: Vec<_> = operations()
+        .into_iter()
+        .filter(|operation| !routes.contains(operation))
+        .collect();
+
+    assert!(
+        unrouted.is_empty(),
+        "Described operations that are not routed: {unrouted:?}"
+    );
+}